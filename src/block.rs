@@ -1,14 +1,46 @@
 pub mod error;
 
 mod block_header;
+mod coinbase;
+mod header_tree;
+mod merkle_branch;
 mod transaction;
+mod version_rolling;
+mod witness;
 
-pub use block_header::BlockHeader;
-pub use transaction::{RawTransaction, Transaction};
+pub use block_header::{BlockHeader, BlockHeaderBytes, MAX_FUTURE_BLOCK_TIME, MEDIAN_TIME_SPAN};
+pub use coinbase::{CoinbaseBuilder, block_subsidy};
+pub use header_tree::{HeaderEntry, HeaderTree, TipChange};
+pub use merkle_branch::MerkleBranch;
+pub use transaction::{Input, OutPoint, Output, RawTransaction, Transaction};
+pub use version_rolling::{DEFAULT_VERSION_ROLLING_MASK, VersionRolling, Versions};
+pub use witness::{WITNESS_COMMITMENT_HEADER, WITNESS_RESERVED_VALUE};
 
-use crate::hash::{Hash, Hash256};
+use std::io::{self, Read, Write};
 
-#[derive(Debug)]
+use crate::{
+    block::error::Error,
+    encoding::{self, Decodable, Encodable},
+    hash::{Hash, Hash256, NaturalBytes, ReverseBytes},
+};
+
+/// # Block
+///
+/// A block header followed by all transactions of the block.
+/// This is the format of the `block` message and of `submitblock`.
+///
+/// ```plaintext
+/// 0100000000000000000000000000000000000000000000000000000000000000
+/// 000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa
+/// 4b1e5e4a29ab5f49ffff001d1dac2b7c ... Block header (80 bytes)
+/// 01 ................................. Transaction count (compact size)
+/// 01000000010000000000000000000000
+/// ...
+/// ac00000000 ......................... Transactions
+/// ```
+///
+/// See [Block](https://learnmeabitcoin.com/technical/block/).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Block {
     header: BlockHeader,
     transactions: Vec<RawTransaction>,
@@ -20,50 +52,431 @@ impl Block {
         prev_block_header_hash: Hash,
         n_bits: u32,
         transactions: Vec<RawTransaction>,
-    ) -> Self {
-        let mut block = Self {
-            header: BlockHeader::new(
-                version,
-                prev_block_header_hash,
-                Self::compute_merkle_root_hash(&transactions),
-                n_bits,
-            ),
+    ) -> Result<Self, Error> {
+        let merkle_root_hash = Self::compute_merkle_root_hash(&transactions)?;
+
+        Ok(Self {
+            header: BlockHeader::new(version, prev_block_header_hash, merkle_root_hash, n_bits),
+            transactions,
+        })
+    }
+
+    /// Assemble a block from a header and its transactions without any validation.
+    pub fn from_parts(header: BlockHeader, transactions: Vec<RawTransaction>) -> Self {
+        Self {
+            header,
             transactions,
-        };
-        block.update_merkle_root_hash();
+        }
+    }
+
+    /// Parse a block in the serialization of [`Self::to_bytes`].
+    ///
+    /// All bytes have to be consumed.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut cursor = bytes;
+        let block = Self::consensus_decode(&mut cursor)
+            .map_err(|e| Error::from_decode(e, bytes.len() - cursor.len()))?;
+
+        if cursor.is_empty() {
+            Ok(block)
+        } else {
+            Err(Error::TrailingBytes {
+                offset: bytes.len() - cursor.len(),
+                count: cursor.len(),
+            })
+        }
+    }
+
+    /// Serialize the header, the transaction count and all transactions.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_consensus_bytes()
+    }
 
-        block
+    /// The hex of [`Self::to_bytes`], as expected by `submitblock`.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.to_bytes())
+    }
+
+    pub fn header(&self) -> &BlockHeader {
+        &self.header
+    }
+
+    pub fn header_mut(&mut self) -> &mut BlockHeader {
+        &mut self.header
+    }
+
+    pub fn transactions(&self) -> &[RawTransaction] {
+        &self.transactions
     }
 
     /// Compute the merkle hash and apply it into the internal [`BlockHeader`].
-    pub fn update_merkle_root_hash(&mut self) {
-        self.header.merkle_root_hash = Self::compute_merkle_root_hash(&self.transactions);
+    pub fn update_merkle_root_hash(&mut self) -> Result<(), Error> {
+        self.header.merkle_root_hash = Self::compute_merkle_root_hash(&self.transactions)?;
+
+        Ok(())
+    }
+
+    /// The merkle branch of the coinbase transaction.
+    ///
+    /// See [`MerkleBranch`].
+    pub fn coinbase_merkle_branch(&self) -> Result<MerkleBranch, Error> {
+        let txids = self
+            .transactions
+            .iter()
+            .map(RawTransaction::txid)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(MerkleBranch::from_txids(&txids))
     }
 
-    /// Computes the merkle hash.
+    /// Replace the coinbase transaction and update the merkle root using `branch`.
     ///
-    /// The merkle root hash is in reverse byte order.
-    pub fn compute_merkle_root_hash(raw_transactions: &Vec<RawTransaction>) -> Hash {
-        let mut hash = String::new();
+    /// `branch` has to be the [`Self::coinbase_merkle_branch`] of this block.
+    /// Only one hash per tree level is computed.
+    pub fn replace_coinbase(
+        &mut self,
+        coinbase: RawTransaction,
+        branch: &MerkleBranch,
+    ) -> Result<(), Error> {
+        let first = self.transactions.first_mut().ok_or(Error::NoTransactions)?;
+        self.header.merkle_root_hash = branch.compute_merkle_root(&coinbase.txid()?);
+        *first = coinbase;
 
-        if raw_transactions.len() == 1 {}
+        Ok(())
+    }
+
+    /// Computes the merkle hash over the TXIDs of `raw_transactions`.
+    ///
+    /// The coinbase transaction has to be the first one.
+    /// Mutated merkle trees (see [`Self::compute_merkle_root`]) are rejected.
+    pub fn compute_merkle_root_hash(raw_transactions: &[RawTransaction]) -> Result<Hash, Error> {
+        if raw_transactions.is_empty() {
+            return Err(Error::NoTransactions);
+        }
+
+        let txids = raw_transactions
+            .iter()
+            .map(RawTransaction::txid)
+            .collect::<Result<Vec<_>, _>>()?;
+        let (merkle_root_hash, mutated) = Self::compute_merkle_root(txids);
+
+        if mutated {
+            Err(Error::MutatedMerkleTree)
+        } else {
+            Ok(merkle_root_hash)
+        }
+    }
 
-        todo!();
+    /// Computes the merkle root over `hashes` and whether the tree is mutated.
+    ///
+    /// On every level with an odd count of hashes the last hash is paired with itself.
+    /// This makes the transaction lists `[a, b, c]` and `[a, b, c, c]` produce the same root (CVE-2012-2459).
+    /// A tree is mutated if two hashes which are paired with each other are equal,
+    /// which is never the case for a valid block.
+    ///
+    /// Returns [`Hash::ZERO`] for no hashes, the same as Bitcoin Core.
+    ///
+    /// See [Merkle Root](https://learnmeabitcoin.com/technical/block/merkle-root/).
+    pub fn compute_merkle_root(mut hashes: Vec<Hash>) -> (Hash, bool) {
+        let mut mutated = false;
 
-        let mut merkle_root_hash = [0u8; 16];
-        hex::encode_to_slice(hash, &mut merkle_root_hash);
+        while hashes.len() > 1 {
+            mutated |= hashes.chunks_exact(2).any(|pair| pair[0] == pair[1]);
 
-        Hash::NaturalByte { hash }
+            if hashes.len() % 2 == 1 {
+                hashes.push(hashes[hashes.len() - 1]);
+            }
+
+            for i in 0..hashes.len() / 2 {
+                hashes[i] = Self::compute_merkle_branch(&hashes[2 * i], &hashes[2 * i + 1]);
+            }
+            hashes.truncate(hashes.len() / 2);
+        }
+
+        (hashes.first().copied().unwrap_or(Hash::ZERO), mutated)
     }
 
-    fn compute_merkle_branch(txid1: Hash, txid2: Hash) -> Hash {
+    pub fn compute_merkle_branch(txid1: &Hash, txid2: &Hash) -> Hash {
         let mut bytes = [0u8; 64];
-        bytes[..32].copy_from_slice(&txid1.to_natural_byte().to_bytes());
-        bytes[32..].copy_from_slice(&txid2.to_natural_byte().to_bytes());
+        bytes[..32].copy_from_slice(txid1.as_bytes());
+        bytes[32..].copy_from_slice(txid2.as_bytes());
         Hash256::digest(&bytes)
     }
 
+    /// The block hash: the Hash256 of the block header.
     pub fn block_header_hash256(&self) -> Hash {
-        todo!()
+        self.header.hash()
+    }
+
+    /// The block hash in internal byte order, as used in the next block header.
+    ///
+    /// > internal byte order
+    pub fn natural_block_hash(&self) -> NaturalBytes {
+        self.block_header_hash256().natural_bytes()
+    }
+
+    /// The block hash in reverse byte order, as shown by block explorers.
+    ///
+    /// > reverse byte order
+    pub fn reverse_block_hash(&self) -> ReverseBytes {
+        self.block_header_hash256().reverse_bytes()
+    }
+}
+
+impl Encodable for Block {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        Ok(self.header.consensus_encode(writer)? + self.transactions.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for Block {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, encoding::error::Error> {
+        Ok(Self {
+            header: BlockHeader::consensus_decode(reader)?,
+            transactions: Vec::consensus_decode(reader)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod block_test {
+    use crate::{block::error::Error, hash::Hash};
+
+    use super::{Block, MerkleBranch, RawTransaction};
+
+    /// The coinbase transaction of the genesis block.
+    const GENESIS_COINBASE: &str = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000";
+    /// The coinbase transaction of block 170.
+    const BLOCK_170_COINBASE: &str = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff0704ffff001d0102ffffffff0100f2052a01000000434104d46c4968bde02899d2aa0963367c7a6ce34eec332b32e42e5f3407e052d64ac625da6f0718e7b302140434bd725706957c092db53805b821a85b23a7ac61725bac00000000";
+    /// The first transaction between two people, included in block 170.
+    const BLOCK_170_TRANSACTION: &str = "0100000001c997a5e56e104102fa209c6a852dd90660a20b2d9c352423edce25857fcd3704000000004847304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901ffffffff0200ca9a3b00000000434104ae1a62fe09c5f51b13905f07f06b99a2f7159b2225f374cd378d71302fa28414e7aab37397f554a7df5f142c21c1b7303b8a0626f1baded5c72a704f7e6cd84cac00286bee0000000043410411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3ac00000000";
+
+    /// The header of the genesis block.
+    const GENESIS_HEADER: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
+    /// The header of block 170.
+    const BLOCK_170_HEADER: &str = "0100000055bd840a78798ad0da853f68974f3d183e2bd1db6a842c1feecf222a00000000ff104ccb05421ab93e63f8c3ce5c2c2e9dbb37de2764b3a3175c8166562cac7d51b96a49ffff001d283e9e70";
+
+    fn raw_transaction(hex: &str) -> RawTransaction {
+        RawTransaction::new(hex::decode(hex).unwrap())
+    }
+
+    fn txids(hashes: &[&str]) -> Vec<Hash> {
+        hashes.iter().map(|hash| hash.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn merkle_root_from_raw_transactions() {
+        let genesis = [raw_transaction(GENESIS_COINBASE)];
+        assert_eq!(
+            "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+            Block::compute_merkle_root_hash(&genesis)
+                .unwrap()
+                .to_string()
+        );
+
+        let block_170 = [
+            raw_transaction(BLOCK_170_COINBASE),
+            raw_transaction(BLOCK_170_TRANSACTION),
+        ];
+        assert_eq!(
+            "7dac2c5666815c17a3b36427de37bb9d2e2c5ccec3f8633eb91a4205cb4c10ff",
+            Block::compute_merkle_root_hash(&block_170)
+                .unwrap()
+                .to_string()
+        );
+
+        assert!(matches!(
+            Block::compute_merkle_root_hash(&[]),
+            Err(Error::NoTransactions)
+        ));
+        let mutated = [
+            raw_transaction(BLOCK_170_COINBASE),
+            raw_transaction(BLOCK_170_TRANSACTION),
+            raw_transaction(BLOCK_170_COINBASE),
+            raw_transaction(BLOCK_170_COINBASE),
+        ];
+        assert!(matches!(
+            Block::compute_merkle_root_hash(&mutated),
+            Err(Error::MutatedMerkleTree)
+        ));
+    }
+
+    #[test]
+    fn merkle_root_one_transaction() {
+        // Genesis block.
+        let hashes = txids(&["4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"]);
+        let (root, mutated) = Block::compute_merkle_root(hashes);
+
+        assert_eq!(
+            "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+            root.to_string()
+        );
+        assert!(!mutated);
+    }
+
+    #[test]
+    fn merkle_root_two_transactions() {
+        // Block 170.
+        let hashes = txids(&[
+            "b1fea52486ce0c62bb442b530a3f0132b826c74e473d1f2c220bfa78111c5082",
+            "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16",
+        ]);
+        let (root, mutated) = Block::compute_merkle_root(hashes);
+
+        assert_eq!(
+            "7dac2c5666815c17a3b36427de37bb9d2e2c5ccec3f8633eb91a4205cb4c10ff",
+            root.to_string()
+        );
+        assert!(!mutated);
+    }
+
+    #[test]
+    fn merkle_root_four_transactions() {
+        // Block 100000.
+        let hashes = txids(&[
+            "8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+            "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+            "6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+            "e9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d",
+        ]);
+        let (root, mutated) = Block::compute_merkle_root(hashes);
+
+        assert_eq!(
+            "f3e94742aca4b5ef85488dc37c06c3282295ffec960994b2c0d5ac2a25a95766",
+            root.to_string()
+        );
+        assert!(!mutated);
+    }
+
+    #[test]
+    fn merkle_root_odd_and_many_transactions() {
+        for count in [3usize, 5, 7, 11, 64, 255] {
+            let hashes = (0..count)
+                .map(|i| {
+                    let mut bytes = [0u8; 32];
+                    bytes[..8].copy_from_slice(&(i as u64).to_le_bytes());
+                    Hash::from_natural_bytes(bytes)
+                })
+                .collect::<Vec<_>>();
+
+            let (root, mutated) = Block::compute_merkle_root(hashes.clone());
+            assert_eq!(naive_merkle_root(&hashes), root, "{} transactions", count);
+            assert!(!mutated, "{} transactions", count);
+        }
+    }
+
+    #[test]
+    fn merkle_root_mutated() {
+        let hashes = txids(&[
+            "8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+            "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+            "6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+        ]);
+        let mut duplicated = hashes.clone();
+        duplicated.push(hashes[2]);
+
+        let (root, mutated) = Block::compute_merkle_root(hashes);
+        assert!(!mutated);
+
+        let (duplicated_root, mutated) = Block::compute_merkle_root(duplicated);
+        assert_eq!(root, duplicated_root);
+        assert!(mutated);
+    }
+
+    #[test]
+    fn genesis_block() {
+        let hex = format!("{}01{}", GENESIS_HEADER, GENESIS_COINBASE);
+        let block = Block::from_bytes(&hex::decode(&hex).unwrap()).unwrap();
+
+        assert_eq!(1, block.transactions().len());
+        assert_eq!(
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+            block.block_header_hash256().to_string()
+        );
+        assert_eq!(
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+            hex::encode(block.reverse_block_hash().0)
+        );
+        assert_eq!(
+            "6fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000",
+            hex::encode(block.natural_block_hash().0)
+        );
+        assert_eq!(
+            Block::compute_merkle_root_hash(block.transactions()).unwrap(),
+            block.header().merkle_root_hash
+        );
+        assert_eq!(hex, block.to_hex());
+    }
+
+    #[test]
+    fn block_170() {
+        let hex = format!(
+            "{}02{}{}",
+            BLOCK_170_HEADER, BLOCK_170_COINBASE, BLOCK_170_TRANSACTION
+        );
+        let block = Block::from_bytes(&hex::decode(&hex).unwrap()).unwrap();
+
+        assert_eq!(2, block.transactions().len());
+        assert_eq!(
+            "00000000d1145790a8694403d4063f323d499e655c83426834d4ce2f8dd4a2ee",
+            block.block_header_hash256().to_string()
+        );
+        assert_eq!(
+            "000000002a22cfee1f2c846adbd12b3e183d4f97683f85dad08a79780a84bd55",
+            block.header().prev_block_header_hash.to_string()
+        );
+        assert_eq!(
+            Block::compute_merkle_root_hash(block.transactions()).unwrap(),
+            block.header().merkle_root_hash
+        );
+        assert_eq!(hex, block.to_hex());
+
+        let rebuilt = Block::from_parts(block.header().clone(), block.transactions().to_vec());
+        assert_eq!(block.to_bytes(), rebuilt.to_bytes());
+    }
+
+    #[test]
+    fn block_parse_errors() {
+        let bytes = hex::decode(format!("{}01{}", GENESIS_HEADER, GENESIS_COINBASE)).unwrap();
+
+        assert!(matches!(
+            Block::from_bytes(&bytes[..79]),
+            Err(Error::UnexpectedEnd { offset: 79, .. })
+        ));
+        assert!(matches!(
+            Block::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(Error::UnexpectedEnd { .. })
+        ));
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(
+            Block::from_bytes(&trailing),
+            Err(Error::TrailingBytes { count: 1, .. })
+        ));
+
+        // A block without transactions decodes, but has no coinbase to replace.
+        let mut empty =
+            Block::from_bytes(&hex::decode(format!("{}00", GENESIS_HEADER)).unwrap()).unwrap();
+        let coinbase = raw_transaction(GENESIS_COINBASE);
+        let branch = MerkleBranch::from_txids(&[coinbase.txid().unwrap()]);
+        assert!(matches!(
+            empty.replace_coinbase(coinbase, &branch),
+            Err(Error::NoTransactions)
+        ));
+    }
+
+    /// A recursive merkle root for comparison.
+    fn naive_merkle_root(hashes: &[Hash]) -> Hash {
+        if hashes.len() == 1 {
+            return hashes[0];
+        }
+
+        let next = hashes
+            .chunks(2)
+            .map(|pair| Block::compute_merkle_branch(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect::<Vec<_>>();
+
+        naive_merkle_root(&next)
     }
 }
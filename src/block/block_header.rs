@@ -1,8 +1,21 @@
 mod block_header_bytes;
 
+use std::io::{self, Read, Write};
+
 pub use block_header_bytes::BlockHeaderBytes;
 
-use crate::{get_unix_timestamp, hash::Hash};
+use crate::{
+    block::error::Error,
+    encoding::{self, Decodable, Encodable},
+    get_unix_timestamp,
+    hash::{Hash, Hash256},
+    u256::U256,
+};
+
+/// The maximum seconds a block time may be ahead of the validating node´s clock.
+pub const MAX_FUTURE_BLOCK_TIME: u32 = 2 * 60 * 60;
+/// The count of previous blocks the median time past is computed over.
+pub const MEDIAN_TIME_SPAN: usize = 11;
 
 /// # BlockHeader
 ///
@@ -23,7 +36,7 @@ use crate::{get_unix_timestamp, hash::Hash};
 /// ```
 ///
 /// See [Block Header](https://learnmeabitcoin.com/technical/block/#header).
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct BlockHeader {
     /// The version of this block header.#
     /// Indicates the validation rules.
@@ -32,7 +45,7 @@ pub struct BlockHeader {
     pub version: i32,
     /// A SHA256(SHA256()) hash in internal byte order of the previous block´s header.
     ///
-    /// > internal byte order; stored in reversed byte order.
+    /// > internal byte order
     pub prev_block_header_hash: Hash,
     /// A SHA256(SHA256()) hash in internal byte order.
     /// Its derived from the hashes of all transactions included in this block.
@@ -49,7 +62,7 @@ pub struct BlockHeader {
     ///
     /// [Merkle Root](https://learnmeabitcoin.com/technical/block/merkle-root/)
     ///
-    /// > internal byte order; stored in reversed byte order.
+    /// > internal byte order
     pub merkle_root_hash: Hash,
     /// The block time is a Unix epock time when the miner started hashing the header (according to the miner).
     /// Must be strictly greater than the median time of the previous 11 blocks.
@@ -82,11 +95,13 @@ impl BlockHeader {
         n_bits: u32,
     ) -> Self {
         let time = get_unix_timestamp()
-                        .expect(&format!(
-                            "Failed to get a valid Unix timestamp for new BlockHeader with previous block header hash: {}",
-                            prev_block_header_hash.as_str()
-                        ))
-                        .as_secs() as u32;
+            .unwrap_or_else(|e| {
+                panic!(
+                    "Failed to get a valid Unix timestamp for new BlockHeader with previous block header hash {}: {}",
+                    prev_block_header_hash, e
+                )
+            })
+            .as_secs() as u32;
 
         Self {
             version,
@@ -98,36 +113,203 @@ impl BlockHeader {
         }
     }
 
+    /// Parse a serialized header. The inverse of [`Self::as_bytes`].
+    pub fn from_bytes(bytes: &BlockHeaderBytes) -> Self {
+        Self {
+            version: bytes.get_version(),
+            prev_block_header_hash: bytes.get_prev_block_header_hash(),
+            merkle_root_hash: bytes.get_merkle_root_hash(),
+            time: bytes.get_time(),
+            target: bytes.get_target(),
+            nonce: bytes.get_nonce(),
+        }
+    }
+
+    /// Parse a header from the hex of its serialization.
+    ///
+    /// See [`BlockHeaderBytes::from_hex`].
+    pub fn from_hex(hex: &str) -> Result<Self, Error> {
+        Ok(Self::from_bytes(&BlockHeaderBytes::from_hex(hex)?))
+    }
+
     /// Convert the [`BlockHeader`] into a valid byte array.
     pub fn as_bytes(&self) -> BlockHeaderBytes {
         let mut bytes = [0u8; 80];
-
-        bytes[..4].copy_from_slice(&self.version.to_le_bytes());
-        bytes[4..36].copy_from_slice(
-            &self
-                .prev_block_header_hash
-                .clone()
-                .to_natural_byte()
-                .to_bytes(),
-        );
-        bytes[36..68].copy_from_slice(&self.merkle_root_hash.clone().to_natural_byte().to_bytes());
-        bytes[68..72].copy_from_slice(&self.time.to_le_bytes());
-        bytes[72..76].copy_from_slice(&self.target.to_le_bytes());
-        bytes[76..80].copy_from_slice(&self.nonce.to_le_bytes());
+        self.consensus_encode(&mut bytes.as_mut_slice())
+            .expect("A block header is exactly 80 bytes.");
 
         BlockHeaderBytes::new(bytes)
     }
 
-    pub fn get_target(&self) -> [u8; 256] {
-        todo!()
+    /// The Hash256 of this header.
+    pub fn hash(&self) -> Hash {
+        Hash256::digest(self.as_bytes().as_bytes())
+    }
+
+    /// Decode the compact [`BlockHeader::target`] into the full 256-bit target.
+    ///
+    /// Negative, overflowing and zero targets are rejected the same way as Bitcoin Core does.
+    pub fn get_target(&self) -> Result<U256, Error> {
+        let decoded = U256::from_compact(self.target);
+
+        if decoded.negative {
+            Err(Error::NegativeTarget(self.target))
+        } else if decoded.overflow {
+            Err(Error::TargetOverflow(self.target))
+        } else if decoded.target.is_zero() {
+            Err(Error::ZeroTarget(self.target))
+        } else {
+            Ok(decoded.target)
+        }
+    }
+
+    /// The difficulty relative to the difficulty 1 target (`0x1d00ffff`).
+    ///
+    /// Computed from the compact target the same way as Bitcoin Core´s `GetDifficulty`.
+    pub fn difficulty(&self) -> f64 {
+        let mut shift = (self.target >> 24) & 0xFF;
+        let mut difficulty = 0x0000_FFFF as f64 / (self.target & 0x00FF_FFFF) as f64;
+
+        while shift < 29 {
+            difficulty *= 256.0;
+            shift += 1;
+        }
+        while shift > 29 {
+            difficulty /= 256.0;
+            shift -= 1;
+        }
+
+        difficulty
+    }
+
+    /// The expected count of hashes to find a header with this target: `2^256 / (target + 1)`,
+    /// the same as Bitcoin Core´s `GetBlockProof`.
+    ///
+    /// Computed as `!target / (target + 1) + 1`, as `2^256` does not fit into 256 bits.
+    /// Invalid targets have no work.
+    pub fn work(&self) -> U256 {
+        match self.get_target() {
+            Ok(target) => (!target / (target + U256::ONE)) + U256::ONE,
+            Err(_) => U256::ZERO,
+        }
+    }
+
+    /// Whether `hash` is less than or equal to the target of this header.
+    ///
+    /// Use this with hashes from a [`HeaderHasher`](crate::hash::HeaderHasher) to check a nonce.
+    pub fn meets_target(&self, hash: &Hash) -> Result<bool, Error> {
+        Ok(U256::from(*hash) <= self.get_target()?)
+    }
+
+    /// Whether the hash of this header is less than or equal to its target.
+    pub fn check_proof_of_work(&self) -> Result<bool, Error> {
+        self.meets_target(&self.hash())
+    }
+
+    /// Validate a header received from a peer or pool.
+    ///
+    /// - `pow_limit`: the easiest target of the network, see [`Self::validate_proof_of_work`].
+    /// - `prev_headers`: the headers before this one in chain order, oldest first.
+    ///   Only the last [`MEDIAN_TIME_SPAN`] are used.
+    /// - `now`: the Unix time of the validating clock.
+    pub fn validate(
+        &self,
+        pow_limit: &U256,
+        prev_headers: &[BlockHeader],
+        now: u32,
+    ) -> Result<(), Error> {
+        self.validate_proof_of_work(pow_limit)?;
+        self.validate_time(prev_headers, now)
+    }
+
+    /// Validate the target and the hash against it, the same as Bitcoin Core´s `CheckProofOfWork`.
+    ///
+    /// The target has to be valid and must not be above `pow_limit`.
+    pub fn validate_proof_of_work(&self, pow_limit: &U256) -> Result<(), Error> {
+        let target = self.get_target()?;
+        if &target > pow_limit {
+            return Err(Error::TargetAbovePowLimit(self.target));
+        }
+
+        let hash = self.hash();
+        if U256::from(hash) > target {
+            return Err(Error::InsufficientProofOfWork { hash, target });
+        }
+
+        Ok(())
+    }
+
+    /// Validate the time against the median time past and the future limit.
+    ///
+    /// The time has to be strictly greater than [`Self::median_time_past`] of `prev_headers`
+    /// and at most [`MAX_FUTURE_BLOCK_TIME`] seconds after `now`.
+    /// Without previous headers (genesis) only the future limit is checked.
+    pub fn validate_time(&self, prev_headers: &[BlockHeader], now: u32) -> Result<(), Error> {
+        if let Some(median_time_past) = Self::median_time_past(prev_headers)
+            && self.time <= median_time_past
+        {
+            return Err(Error::TimeTooOld {
+                time: self.time,
+                median_time_past,
+            });
+        }
+
+        let max_time = now.saturating_add(MAX_FUTURE_BLOCK_TIME);
+        if self.time > max_time {
+            return Err(Error::TimeTooNew {
+                time: self.time,
+                max_time,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// The median time of the last [`MEDIAN_TIME_SPAN`] headers, the same as Bitcoin Core´s `GetMedianTimePast`.
+    ///
+    /// `headers` are in chain order, oldest first.
+    /// Returns [`None`] for no headers.
+    pub fn median_time_past(headers: &[BlockHeader]) -> Option<u32> {
+        let start = headers.len().saturating_sub(MEDIAN_TIME_SPAN);
+        let mut times = headers[start..]
+            .iter()
+            .map(|header| header.time)
+            .collect::<Vec<_>>();
+        times.sort_unstable();
+
+        times.get(times.len() / 2).copied()
+    }
+}
+
+impl Encodable for BlockHeader {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        Ok(self.version.consensus_encode(writer)?
+            + self.prev_block_header_hash.consensus_encode(writer)?
+            + self.merkle_root_hash.consensus_encode(writer)?
+            + self.time.consensus_encode(writer)?
+            + self.target.consensus_encode(writer)?
+            + self.nonce.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for BlockHeader {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, encoding::error::Error> {
+        Ok(Self {
+            version: i32::consensus_decode(reader)?,
+            prev_block_header_hash: Hash::consensus_decode(reader)?,
+            merkle_root_hash: Hash::consensus_decode(reader)?,
+            time: u32::consensus_decode(reader)?,
+            target: u32::consensus_decode(reader)?,
+            nonce: u32::consensus_decode(reader)?,
+        })
     }
 }
 
 #[cfg(test)]
 mod block_header_test {
-    use crate::hash::Hash;
+    use crate::{block::error::Error, hash::Hash, u256::U256};
 
-    use super::BlockHeader;
+    use super::{BlockHeader, MAX_FUTURE_BLOCK_TIME};
 
     #[test]
     fn into_bytes() {
@@ -136,15 +318,239 @@ mod block_header_test {
         assert_eq!([0u8; 80], bytes);
     }
 
+    #[test]
+    fn genesis_proof_of_work() {
+        let header = get_genesis_block_header();
+
+        assert_eq!(
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+            header.hash().to_string()
+        );
+        assert!(header.check_proof_of_work().unwrap());
+        assert_eq!(1.0, header.difficulty());
+
+        let mut header = header;
+        header.nonce += 1;
+        assert!(!header.check_proof_of_work().unwrap());
+    }
+
+    #[test]
+    fn validate_proof_of_work() {
+        let mut pow_limit = [0xFFu8; 32];
+        pow_limit[..4].fill(0);
+        let pow_limit = U256::from_be_bytes(pow_limit);
+
+        let header = BlockHeader::from_hex(BLOCK_100000_HEADER).unwrap();
+        assert!(header.validate_proof_of_work(&pow_limit).is_ok());
+        assert!(
+            get_genesis_block_header()
+                .validate_proof_of_work(&pow_limit)
+                .is_ok()
+        );
+
+        let mut easy = header.clone();
+        easy.target = 0x1E00_FFFF;
+        assert!(matches!(
+            easy.validate_proof_of_work(&pow_limit),
+            Err(Error::TargetAbovePowLimit(0x1E00_FFFF))
+        ));
+
+        let mut invalid = header.clone();
+        invalid.nonce += 1;
+        assert!(matches!(
+            invalid.validate_proof_of_work(&pow_limit),
+            Err(Error::InsufficientProofOfWork { .. })
+        ));
+
+        let mut negative = header;
+        negative.target = 0x0492_3456;
+        assert!(matches!(
+            negative.validate_proof_of_work(&pow_limit),
+            Err(Error::NegativeTarget(_))
+        ));
+    }
+
+    #[test]
+    fn median_time_past() {
+        let headers = [5, 1, 9, 3, 7, 2, 8, 4, 6, 10, 0, 100]
+            .into_iter()
+            .map(|time| BlockHeader {
+                time,
+                ..get_zeroed_block_header()
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(None, BlockHeader::median_time_past(&[]));
+        assert_eq!(Some(5), BlockHeader::median_time_past(&headers[..1]));
+        assert_eq!(Some(5), BlockHeader::median_time_past(&headers[..2]));
+        assert_eq!(Some(5), BlockHeader::median_time_past(&headers[..3]));
+        // Only the last 11 headers count: 1..=10 and 0, 100.
+        assert_eq!(Some(6), BlockHeader::median_time_past(&headers));
+    }
+
+    #[test]
+    fn validate_time() {
+        let prev_headers = (0..11)
+            .map(|i| BlockHeader {
+                time: 1_000 + i * 600,
+                ..get_zeroed_block_header()
+            })
+            .collect::<Vec<_>>();
+        // The median is 1_000 + 5 * 600.
+        let now = 10_000;
+        let mut header = get_zeroed_block_header();
+
+        header.time = 4_000;
+        assert!(matches!(
+            header.validate_time(&prev_headers, now),
+            Err(Error::TimeTooOld {
+                time: 4_000,
+                median_time_past: 4_000
+            })
+        ));
+
+        header.time = 4_001;
+        assert!(header.validate_time(&prev_headers, now).is_ok());
+
+        header.time = now + MAX_FUTURE_BLOCK_TIME;
+        assert!(header.validate_time(&prev_headers, now).is_ok());
+
+        header.time += 1;
+        assert!(matches!(
+            header.validate_time(&prev_headers, now),
+            Err(Error::TimeTooNew { .. })
+        ));
+        assert!(header.validate_time(&[], now + 1).is_ok());
+    }
+
+    #[test]
+    fn work() {
+        let mut header = get_genesis_block_header();
+        assert_eq!(U256::from_u64(0x0001_0001_0001), header.work());
+
+        header.target = 0x207F_FFFF;
+        assert_eq!(U256::from_u64(2), header.work());
+
+        header.target = 0x1B04_864C;
+        assert_eq!(U256::from_u64(0x3894_6224_E37E), header.work());
+
+        header.target = 0;
+        assert_eq!(U256::ZERO, header.work());
+    }
+
+    #[test]
+    fn difficulty() {
+        let mut header = get_zeroed_block_header();
+
+        header.target = 0x1B04_04CB;
+        assert!((header.difficulty() - 16_307.420_938_523_983).abs() < 1e-9);
+
+        header.target = 0x1CF8_8F6F;
+        assert!((header.difficulty() - 1.029_916).abs() < 1e-6);
+    }
+
+    #[test]
+    fn invalid_targets() {
+        let mut header = get_zeroed_block_header();
+
+        assert!(matches!(header.get_target(), Err(Error::ZeroTarget(0))));
+
+        header.target = 0x0492_3456;
+        assert!(matches!(header.get_target(), Err(Error::NegativeTarget(_))));
+
+        header.target = 0xFF12_3456;
+        assert!(matches!(header.get_target(), Err(Error::TargetOverflow(_))));
+    }
+
+    /// Block 100000.
+    const BLOCK_100000_HEADER: &str = "0100000050120119172a610421a6c3011dd330d9df07b63616c2cc1f1cd00200000000006657a9252aacd5c0b2940996ecff952228c3067cc38d4885efb5a4ac4247e9f337221b4d4c86041b0f2b5710";
+    /// A mainnet header from 2024 with version rolling bits set.
+    const VERSION_ROLLING_HEADER: &str = "0060b0329fd61df7a284ba2f7debbfaef9c5152271ef8165037300000000000000000000562139850fcfc2eb3204b1e790005aaba44e63a2633252fdbced58d2a9a87e2cdb34cf665b250317245ddc6a";
+
+    #[test]
+    fn from_bytes() {
+        let header = get_genesis_block_header();
+        let bytes = header.as_bytes();
+
+        assert_eq!(1, bytes.get_version());
+        assert_eq!(
+            header.prev_block_header_hash,
+            bytes.get_prev_block_header_hash()
+        );
+        assert_eq!(header.merkle_root_hash, bytes.get_merkle_root_hash());
+        assert_eq!(1_231_006_505, bytes.get_time());
+        assert_eq!(0x1D00_FFFF, bytes.get_target());
+        assert_eq!(2_083_236_893, bytes.get_nonce());
+        assert_eq!(header, BlockHeader::from_bytes(&bytes));
+    }
+
+    #[test]
+    fn from_hex_round_trip() {
+        let header = BlockHeader::from_hex(BLOCK_100000_HEADER).unwrap();
+        assert_eq!(1, header.version);
+        assert_eq!(1_293_623_863, header.time);
+        assert_eq!(0x1B04_864C, header.target);
+        assert_eq!(274_148_111, header.nonce);
+        assert_eq!(
+            "000000000002d01c1fccc21636b607dfd930d31d01c3a62104612a1719011250",
+            header.prev_block_header_hash.to_string()
+        );
+        assert_eq!(
+            "f3e94742aca4b5ef85488dc37c06c3282295ffec960994b2c0d5ac2a25a95766",
+            header.merkle_root_hash.to_string()
+        );
+        assert_eq!(
+            "000000000003ba27aa200b1cecaad478d2b00432346c3f1f3986da1afd33e506",
+            header.hash().to_string()
+        );
+        assert_eq!(
+            BLOCK_100000_HEADER,
+            hex::encode(header.as_bytes().as_bytes())
+        );
+
+        let header = BlockHeader::from_hex(VERSION_ROLLING_HEADER).unwrap();
+        assert_eq!(0x32B0_6000, header.version);
+        assert_eq!(
+            "00000000000000000000fcddd3a12dff20bb1a246e073b3d7caccb0453e24ac2",
+            header.hash().to_string()
+        );
+        assert!(header.check_proof_of_work().unwrap());
+        assert_eq!(
+            VERSION_ROLLING_HEADER,
+            hex::encode(header.as_bytes().as_bytes())
+        );
+    }
+
+    #[test]
+    fn from_hex_errors() {
+        assert!(matches!(
+            BlockHeader::from_hex(&BLOCK_100000_HEADER[..158]),
+            Err(Error::InvalidBlockHeaderSize(79))
+        ));
+        assert!(matches!(
+            BlockHeader::from_hex("zz"),
+            Err(Error::InvalidHex(_))
+        ));
+    }
+
+    fn get_genesis_block_header() -> BlockHeader {
+        BlockHeader {
+            version: 1,
+            prev_block_header_hash: Hash::ZERO,
+            merkle_root_hash: "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"
+                .parse()
+                .unwrap(),
+            time: 1_231_006_505,
+            target: 0x1D00_FFFF,
+            nonce: 2_083_236_893,
+        }
+    }
+
     fn get_zeroed_block_header() -> BlockHeader {
         BlockHeader {
             version: 0,
-            prev_block_header_hash: Hash::from_natural_byte_str(
-                "0000000000000000000000000000000000000000000000000000000000000000",
-            ),
-            merkle_root_hash: Hash::from_natural_byte_str(
-                "0000000000000000000000000000000000000000000000000000000000000000",
-            ),
+            prev_block_header_hash: Hash::ZERO,
+            merkle_root_hash: Hash::ZERO,
             time: 0,
             target: 0,
             nonce: 0,
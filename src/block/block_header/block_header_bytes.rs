@@ -1,35 +1,107 @@
+use crate::{block::error::Error, hash::Hash};
+
 type BlockHeaderType = [u8; 80];
 
+/// A serialized [`BlockHeader`](super::BlockHeader).
+///
+/// ```plaintext
+/// 0..4 ............................... Version
+/// 4..36 .............................. Previous block header hash
+/// 36..68 ............................. Merkle root
+/// 68..72 ............................. Time
+/// 72..76 ............................. Target (nBits)
+/// 76..80 ............................. Nonce
+/// ```
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct BlockHeaderBytes(BlockHeaderType);
 
 impl BlockHeaderBytes {
+    pub const SIZE: usize = 80;
+
+    const VERSION_OFFSET: usize = 0;
+    const PREV_BLOCK_HEADER_HASH_OFFSET: usize = 4;
+    const MERKLE_ROOT_HASH_OFFSET: usize = 36;
+    const TIME_OFFSET: usize = 68;
+    const TARGET_OFFSET: usize = 72;
+    const NONCE_OFFSET: usize = 76;
+
     pub fn new(bytes: BlockHeaderType) -> Self {
         Self(bytes)
     }
 
-    pub fn get_nonce(&mut self) -> u32 {
-        let mut bytes = [0u8; 4];
-        bytes.copy_from_slice(self.get_nonce_ref());
+    /// Parse a header from the hex of its serialization,
+    /// as returned by `getblockheader <hash> false`.
+    pub fn from_hex(hex: &str) -> Result<Self, Error> {
+        Self::try_from(hex::decode(hex)?.as_slice())
+    }
+
+    /// > little-endian
+    pub fn get_version(&self) -> i32 {
+        i32::from_le_bytes(self.get_array(Self::VERSION_OFFSET))
+    }
 
-        u32::from_le_bytes(bytes)
+    /// > internal byte order
+    pub fn get_prev_block_header_hash(&self) -> Hash {
+        Hash::from_natural_bytes(self.get_array(Self::PREV_BLOCK_HEADER_HASH_OFFSET))
+    }
+
+    /// > internal byte order
+    pub fn get_merkle_root_hash(&self) -> Hash {
+        Hash::from_natural_bytes(self.get_array(Self::MERKLE_ROOT_HASH_OFFSET))
+    }
+
+    /// > little-endian
+    pub fn get_time(&self) -> u32 {
+        u32::from_le_bytes(self.get_array(Self::TIME_OFFSET))
+    }
+
+    /// The compact target (nBits).
+    ///
+    /// > little-endian
+    pub fn get_target(&self) -> u32 {
+        u32::from_le_bytes(self.get_array(Self::TARGET_OFFSET))
+    }
+
+    /// > little-endian
+    pub fn get_nonce(&self) -> u32 {
+        u32::from_le_bytes(self.get_array(Self::NONCE_OFFSET))
+    }
+
+    /// See [`VersionRolling`](crate::block::VersionRolling).
+    pub fn set_version(&mut self, version: i32) {
+        self.0[Self::VERSION_OFFSET..Self::VERSION_OFFSET + 4]
+            .copy_from_slice(&version.to_le_bytes());
     }
 
     pub fn set_nonce(&mut self, nonce: u32) {
-        let mut nonce_bytes: [u8; 4] = nonce.to_le_bytes();
-        self.get_nonce_ref().copy_from_slice(&mut nonce_bytes);
+        self.0[Self::NONCE_OFFSET..Self::NONCE_OFFSET + 4].copy_from_slice(&nonce.to_le_bytes());
     }
 
     pub fn to_bytes(&self) -> BlockHeaderType {
-        self.0.clone()
+        self.0
     }
 
     pub fn as_bytes(&self) -> &[u8] {
         &self.0
     }
 
-    fn get_nonce_ref(&mut self) -> &mut [u8] {
-        &mut self.0[76..80]
+    fn get_array<const N: usize>(&self, offset: usize) -> [u8; N] {
+        let mut bytes = [0u8; N];
+        bytes.copy_from_slice(&self.0[offset..offset + N]);
+
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for BlockHeaderBytes {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        Ok(Self(
+            bytes
+                .try_into()
+                .map_err(|_| Error::InvalidBlockHeaderSize(bytes.len()))?,
+        ))
     }
 }
 
@@ -0,0 +1,399 @@
+use crate::{
+    block::{
+        Block, Input, OutPoint, Output, RawTransaction, Transaction, error::Error,
+        witness::WITNESS_RESERVED_VALUE,
+    },
+    chain_params::ChainParams,
+    hash::Hash,
+};
+
+/// The subsidy of the first block in satoshis: 50 BTC.
+pub const INITIAL_SUBSIDY: u64 = 50 * 100_000_000;
+/// The default size of the extranonce slot in bytes.
+pub const DEFAULT_EXTRANONCE_SIZE: usize = 8;
+
+/// # CoinbaseBuilder
+///
+/// Builds the coinbase transaction of a block template.
+///
+/// The scriptSig is laid out as:
+/// ```plaintext
+/// 03 5b7a03 .......................... Block height per BIP34: 227931
+/// 0000000000000000 ................... Extranonce slot
+/// 2f6d696e6572722f ................... Coinbase tag: "/minerr/"
+/// ```
+///
+/// If any other transaction of the block has a witness,
+/// the BIP141 witness commitment output is added
+/// and the coinbase gets the witness reserved value as its witness.
+///
+/// See [Coinbase Transaction](https://learnmeabitcoin.com/technical/mining/coinbase-transaction/).
+#[derive(Debug, Clone)]
+pub struct CoinbaseBuilder {
+    height: u32,
+    extranonce: Vec<u8>,
+    tag: Vec<u8>,
+    payouts: Vec<Output>,
+    fees: u64,
+    subsidy_halving_interval: u32,
+    /// The WTXIDs of all other transactions of the block, in order.
+    wtxids: Vec<Hash>,
+    has_witness: bool,
+}
+
+impl CoinbaseBuilder {
+    /// The smallest allowed scriptSig size.
+    pub const MIN_SCRIPT_SIG_SIZE: usize = 2;
+    /// The largest allowed scriptSig size.
+    pub const MAX_SCRIPT_SIG_SIZE: usize = 100;
+
+    /// A coinbase for the block at `height` of the network of `params`.
+    pub fn new(height: u32, params: &ChainParams) -> Self {
+        Self {
+            height,
+            extranonce: vec![0u8; DEFAULT_EXTRANONCE_SIZE],
+            tag: Vec::new(),
+            payouts: Vec::new(),
+            fees: 0,
+            subsidy_halving_interval: params.subsidy_halving_interval,
+            wtxids: Vec::new(),
+            has_witness: false,
+        }
+    }
+
+    /// Reserve an extranonce slot of `size` zeroed bytes.
+    pub fn extranonce_size(mut self, size: usize) -> Self {
+        self.extranonce = vec![0u8; size];
+        self
+    }
+
+    /// Set the initial extranonce. The slot size is the length of `extranonce`.
+    pub fn extranonce(mut self, extranonce: Vec<u8>) -> Self {
+        self.extranonce = extranonce;
+        self
+    }
+
+    /// An arbitrary tag placed after the extranonce.
+    pub fn tag(mut self, tag: Vec<u8>) -> Self {
+        self.tag = tag;
+        self
+    }
+
+    /// Add an output paying `value` satoshis to `script_pubkey`.
+    pub fn payout(mut self, script_pubkey: Vec<u8>, value: u64) -> Self {
+        self.payouts.push(Output {
+            value,
+            script_pubkey,
+        });
+        self
+    }
+
+    /// The sum of all fees of the block´s transactions in satoshis.
+    pub fn fees(mut self, fees: u64) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    /// All other transactions of the block in order, without the coinbase.
+    ///
+    /// Used for the witness commitment.
+    pub fn transactions(mut self, raw_transactions: &[RawTransaction]) -> Self {
+        self.wtxids = raw_transactions.iter().map(RawTransaction::wtxid).collect();
+        self.has_witness = raw_transactions.iter().any(RawTransaction::is_segwit);
+        self
+    }
+
+    /// The block subsidy in satoshis at this height.
+    pub fn subsidy(&self) -> u64 {
+        block_subsidy(self.height, self.subsidy_halving_interval)
+    }
+
+    /// The byte offset of the extranonce slot in the output of [`Self::build`].
+    pub fn extranonce_offset(&self) -> usize {
+        // version + (marker + flag) + input count + outpoint + scriptSig length + height
+        // The scriptSig is at most 100 bytes, so its length is a single byte.
+        let witness_len = if self.has_witness { 2 } else { 0 };
+
+        4 + witness_len + 1 + 36 + 1 + encode_height(self.height).len()
+    }
+
+    pub fn build(&self) -> Result<RawTransaction, Error> {
+        if self.payouts.is_empty() {
+            return Err(Error::NoPayouts);
+        }
+
+        let expected = self
+            .subsidy()
+            .checked_add(self.fees)
+            .ok_or(Error::AmountOverflow)?;
+        let actual = self
+            .payouts
+            .iter()
+            .try_fold(0u64, |sum, payout| sum.checked_add(payout.value))
+            .ok_or(Error::AmountOverflow)?;
+        if expected != actual {
+            return Err(Error::InvalidPayoutAmount { expected, actual });
+        }
+
+        let script_sig = self.script_sig();
+        if !(Self::MIN_SCRIPT_SIG_SIZE..=Self::MAX_SCRIPT_SIG_SIZE).contains(&script_sig.len()) {
+            return Err(Error::InvalidCoinbaseScriptSize(script_sig.len()));
+        }
+
+        let mut outputs = self.payouts.clone();
+        let mut witness = Vec::new();
+        if self.has_witness {
+            outputs.push(self.witness_commitment_output());
+            witness.push(WITNESS_RESERVED_VALUE.to_vec());
+        }
+
+        let transaction = Transaction {
+            version: 2,
+            inputs: vec![Input {
+                previous_output: OutPoint::NULL,
+                script_sig,
+                sequence: u32::MAX,
+                witness,
+            }],
+            outputs,
+            lock_time: 0,
+        };
+
+        Ok(transaction.into())
+    }
+
+    fn script_sig(&self) -> Vec<u8> {
+        [
+            encode_height(self.height).as_slice(),
+            &self.extranonce,
+            &self.tag,
+        ]
+        .concat()
+    }
+
+    fn witness_commitment_output(&self) -> Output {
+        // The coinbase WTXID is replaced by zero.
+        let wtxids = [Hash::ZERO]
+            .into_iter()
+            .chain(self.wtxids.iter().copied())
+            .collect();
+        let (witness_merkle_root, _) = Block::compute_merkle_root(wtxids);
+        let commitment =
+            Block::compute_witness_commitment(&witness_merkle_root, &WITNESS_RESERVED_VALUE);
+
+        Output::new_witness_commitment(&commitment)
+    }
+}
+
+/// The block subsidy in satoshis at `height`.
+///
+/// Halves every `halving_interval` blocks until it reaches zero.
+pub fn block_subsidy(height: u32, halving_interval: u32) -> u64 {
+    let halvings = height / halving_interval;
+    if halvings >= 64 {
+        return 0;
+    }
+
+    INITIAL_SUBSIDY >> halvings
+}
+
+/// Encode the block height as a minimal script push, the same as Bitcoin Core´s `CScript() << height`.
+///
+/// BIP34 requires the coinbase scriptSig to start with it.
+fn encode_height(height: u32) -> Vec<u8> {
+    const OP_0: u8 = 0x00;
+    const OP_1: u8 = 0x51;
+
+    match height {
+        0 => vec![OP_0],
+        1..=16 => vec![OP_1 + height as u8 - 1],
+        _ => {
+            // Minimal little-endian with a sign bit; add a byte if the highest bit is set.
+            let mut number = height.to_le_bytes().to_vec();
+            while number.last() == Some(&0) {
+                number.pop();
+            }
+            if number.last().is_some_and(|byte| byte & 0x80 != 0) {
+                number.push(0x00);
+            }
+
+            [vec![number.len() as u8], number].concat()
+        }
+    }
+}
+
+#[cfg(test)]
+mod coinbase_test {
+    use crate::{
+        block::{
+            Block, Input, OutPoint, Output, RawTransaction, Transaction, WITNESS_COMMITMENT_HEADER,
+            error::Error,
+        },
+        chain_params::ChainParams,
+        hash::Hash,
+    };
+
+    use super::{CoinbaseBuilder, block_subsidy, encode_height};
+
+    const P2WPKH: [u8; 22] = [
+        0x00, 0x14, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11,
+        0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11,
+    ];
+
+    #[test]
+    fn height() {
+        assert_eq!(vec![0x00], encode_height(0));
+        assert_eq!(vec![0x51], encode_height(1));
+        assert_eq!(vec![0x60], encode_height(16));
+        assert_eq!(vec![0x01, 0x11], encode_height(17));
+        assert_eq!(vec![0x01, 0x7F], encode_height(127));
+        assert_eq!(vec![0x02, 0x80, 0x00], encode_height(128));
+        // The BIP34 activation height on mainnet.
+        assert_eq!(vec![0x03, 0x5B, 0x7A, 0x03], encode_height(227_931));
+        assert_eq!(vec![0x03, 0x40, 0xD1, 0x0C], encode_height(840_000));
+    }
+
+    #[test]
+    fn subsidy() {
+        assert_eq!(5_000_000_000, block_subsidy(0, 210_000));
+        assert_eq!(2_500_000_000, block_subsidy(210_000, 210_000));
+        assert_eq!(312_500_000, block_subsidy(840_000, 210_000));
+        assert_eq!(0, block_subsidy(64 * 210_000, 210_000));
+
+        // The interval comes from the network.
+        assert_eq!(
+            2_500_000_000,
+            CoinbaseBuilder::new(150, &ChainParams::REGTEST).subsidy()
+        );
+        assert_eq!(
+            5_000_000_000,
+            CoinbaseBuilder::new(150, &ChainParams::MAINNET).subsidy()
+        );
+    }
+
+    #[test]
+    fn build() {
+        let builder = CoinbaseBuilder::new(840_000, &ChainParams::MAINNET)
+            .extranonce(vec![0xEE; 4])
+            .tag(b"/minerr/".to_vec())
+            .payout(P2WPKH.to_vec(), 312_500_000)
+            .payout(P2WPKH.to_vec(), 1_000);
+        assert!(matches!(
+            builder.build(),
+            Err(Error::InvalidPayoutAmount {
+                expected: 312_500_000,
+                actual: 312_501_000
+            })
+        ));
+
+        let builder = builder.fees(1_000);
+        let raw_transaction = builder.build().unwrap();
+        let transaction = Transaction::try_from(raw_transaction.clone()).unwrap();
+
+        assert!(transaction.is_coinbase());
+        assert!(!transaction.has_witness());
+        assert_eq!(2, transaction.outputs.len());
+        assert_eq!(
+            [
+                &[0x03, 0x40, 0xD1, 0x0C, 0xEE, 0xEE, 0xEE, 0xEE],
+                &b"/minerr/"[..]
+            ]
+            .concat(),
+            transaction.inputs[0].script_sig
+        );
+
+        let offset = builder.extranonce_offset();
+        assert_eq!([0xEE; 4], raw_transaction.data[offset..offset + 4]);
+
+        let block = Block::new(0x2000_0000, Hash::ZERO, 0x1703_4219, vec![raw_transaction]);
+        assert!(block.is_ok());
+    }
+
+    #[test]
+    fn build_errors() {
+        assert!(matches!(
+            CoinbaseBuilder::new(1, &ChainParams::MAINNET).build(),
+            Err(Error::NoPayouts)
+        ));
+        assert!(matches!(
+            CoinbaseBuilder::new(1, &ChainParams::MAINNET)
+                .extranonce_size(0)
+                .payout(P2WPKH.to_vec(), 5_000_000_000)
+                .build(),
+            Err(Error::InvalidCoinbaseScriptSize(1))
+        ));
+        assert!(matches!(
+            CoinbaseBuilder::new(1, &ChainParams::MAINNET)
+                .extranonce_size(100)
+                .payout(P2WPKH.to_vec(), 5_000_000_000)
+                .build(),
+            Err(Error::InvalidCoinbaseScriptSize(101))
+        ));
+        assert!(matches!(
+            CoinbaseBuilder::new(1, &ChainParams::MAINNET)
+                .payout(P2WPKH.to_vec(), u64::MAX)
+                .payout(P2WPKH.to_vec(), u64::MAX)
+                .build(),
+            Err(Error::AmountOverflow)
+        ));
+        assert!(matches!(
+            CoinbaseBuilder::new(1, &ChainParams::MAINNET)
+                .fees(u64::MAX)
+                .payout(P2WPKH.to_vec(), u64::MAX)
+                .build(),
+            Err(Error::AmountOverflow)
+        ));
+    }
+
+    #[test]
+    fn witness_commitment() {
+        let segwit_transaction = RawTransaction::from(Transaction {
+            version: 2,
+            inputs: vec![Input {
+                previous_output: OutPoint {
+                    txid: Hash::from_natural_bytes([0x22; 32]),
+                    vout: 0,
+                },
+                script_sig: Vec::new(),
+                sequence: u32::MAX,
+                witness: vec![vec![0x30; 71], vec![0x02; 33]],
+            }],
+            outputs: vec![Output {
+                value: 10_000,
+                script_pubkey: P2WPKH.to_vec(),
+            }],
+            lock_time: 0,
+        });
+
+        let builder = CoinbaseBuilder::new(840_000, &ChainParams::MAINNET)
+            .payout(P2WPKH.to_vec(), 312_500_000)
+            .transactions(std::slice::from_ref(&segwit_transaction));
+        let raw_transaction = builder.build().unwrap();
+        let transaction = Transaction::try_from(raw_transaction.clone()).unwrap();
+
+        assert!(transaction.has_witness());
+        assert_eq!(vec![vec![0u8; 32]], transaction.inputs[0].witness);
+
+        let commitment = &transaction.outputs[1];
+        assert_eq!(0, commitment.value);
+        assert_eq!(38, commitment.script_pubkey.len());
+        assert_eq!(WITNESS_COMMITMENT_HEADER, commitment.script_pubkey[..6]);
+
+        let offset = builder.extranonce_offset();
+        assert_eq!([0u8; 8], raw_transaction.data[offset..offset + 8]);
+        assert_eq!(
+            [0x03, 0x40, 0xD1, 0x0C],
+            raw_transaction.data[offset - 4..offset]
+        );
+
+        assert!(
+            Block::new(
+                0x2000_0000,
+                Hash::ZERO,
+                0x1703_4219,
+                vec![raw_transaction, segwit_transaction]
+            )
+            .is_ok()
+        );
+    }
+}
@@ -1,5 +1,91 @@
+use crate::{encoding, hash::Hash, u256::U256};
+
+/// The `offset` of parse errors is the byte position at which the error was detected.
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
-    #[error("the Merkle root hash is required but is not available. Please call")]
-    MerkleRootHashRequired,
+    #[error("IO Error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("invalid hex: {0}")]
+    InvalidHex(#[from] hex::FromHexError),
+    #[error("unexpected end of data at byte {offset}: {needed} more bytes needed")]
+    UnexpectedEnd { offset: usize, needed: usize },
+    #[error("{count} trailing bytes at byte {offset}")]
+    TrailingBytes { offset: usize, count: usize },
+    #[error("non-canonical compact size at byte {offset}")]
+    NonCanonicalCompactSize { offset: usize },
+    #[error("compact size {size} at byte {offset} is too large")]
+    OversizedCompactSize { offset: usize, size: u64 },
+    #[error("invalid segwit flag {flag:#04x} at byte {offset}")]
+    InvalidSegwitFlag { offset: usize, flag: u8 },
+    #[error("segwit serialization without any witness at byte {offset}")]
+    SuperfluousWitness { offset: usize },
+    #[error("a block requires at least the coinbase transaction")]
+    NoTransactions,
+    #[error("the merkle tree is mutated: duplicate subtrees produce the same root")]
+    MutatedMerkleTree,
+    #[error("a block header is 80 bytes but got {0} bytes")]
+    InvalidBlockHeaderSize(usize),
+    #[error("a coinbase requires at least one payout")]
+    NoPayouts,
+    #[error("the payouts sum up to {actual} satoshis but subsidy and fees are {expected} satoshis")]
+    InvalidPayoutAmount { expected: u64, actual: u64 },
+    #[error("the payouts or subsidy and fees overflow 64 bits")]
+    AmountOverflow,
+    #[error("the coinbase scriptSig is {0} bytes but has to be 2 to 100 bytes")]
+    InvalidCoinbaseScriptSize(usize),
+    #[error("the target {0:#010x} is negative")]
+    NegativeTarget(u32),
+    #[error("the target {0:#010x} overflows 256 bits")]
+    TargetOverflow(u32),
+    #[error("the target {0:#010x} is zero")]
+    ZeroTarget(u32),
+    #[error("the target {0:#010x} is above the proof of work limit")]
+    TargetAbovePowLimit(u32),
+    #[error("the block hash {hash} is above the target {target:x}")]
+    InsufficientProofOfWork { hash: Hash, target: U256 },
+    #[error("the time {time} is not after the median time past {median_time_past}")]
+    TimeTooOld { time: u32, median_time_past: u32 },
+    #[error("the time {time} is more than two hours in the future, the maximum is {max_time}")]
+    TimeTooNew { time: u32, max_time: u32 },
+    #[error(
+        "the version {version:#010x} differs from the job version {job_version:#010x} outside the mask {mask:#010x}"
+    )]
+    VersionOutsideMask {
+        job_version: i32,
+        version: i32,
+        mask: u32,
+    },
+    #[error("the previous header {0} is unknown")]
+    UnknownPrevHeader(Hash),
+    #[error("the target {actual:#010x} differs from the required target {expected:#010x}")]
+    UnexpectedTarget { expected: u32, actual: u32 },
+    #[error("{needed} previous headers are required but only {actual} were given")]
+    MissingPrevHeaders { needed: usize, actual: usize },
+    #[error("{actual} previous headers are more than exist up to the tip at height {tip_height}")]
+    TooManyPrevHeaders { tip_height: u32, actual: usize },
+    #[error("the witness commitment is {actual} but the transactions commit to {expected}")]
+    WitnessCommitmentMismatch { expected: Hash, actual: Hash },
+    #[error("the coinbase witness has to be a single 32 byte witness reserved value")]
+    InvalidWitnessReservedValue,
+    #[error("the block has witness data but no witness commitment")]
+    UnexpectedWitness,
+    #[error("a coinbase has exactly one input which spends the null outpoint")]
+    NotCoinbase,
+}
+
+impl Error {
+    /// Attach the byte `offset` to a decoding error.
+    pub fn from_decode(error: encoding::error::Error, offset: usize) -> Self {
+        use encoding::error::Error as DecodeError;
+
+        match error {
+            DecodeError::IO(e) => Self::IO(e),
+            DecodeError::UnexpectedEnd { needed } => Self::UnexpectedEnd { offset, needed },
+            DecodeError::TrailingBytes(count) => Self::TrailingBytes { offset, count },
+            DecodeError::NonCanonicalCompactSize(_) => Self::NonCanonicalCompactSize { offset },
+            DecodeError::OversizedCompactSize(size) => Self::OversizedCompactSize { offset, size },
+            DecodeError::InvalidSegwitFlag(flag) => Self::InvalidSegwitFlag { offset, flag },
+            DecodeError::SuperfluousWitness => Self::SuperfluousWitness { offset },
+        }
+    }
 }
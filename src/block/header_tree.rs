@@ -0,0 +1,523 @@
+use std::collections::HashMap;
+
+use crate::{
+    block::{BlockHeader, MEDIAN_TIME_SPAN, error::Error},
+    chain_params::ChainParams,
+    hash::Hash,
+    u256::U256,
+};
+
+/// A header in the [`HeaderTree`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderEntry {
+    pub header: BlockHeader,
+    pub hash: Hash,
+    pub height: u32,
+    /// The work of this header and all its ancestors, see [`BlockHeader::work`].
+    pub chainwork: U256,
+}
+
+/// A change of the most-work tip.
+///
+/// If the previous tip is an ancestor of the new tip, `disconnected` is empty.
+/// Otherwise the active chain reorganized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TipChange {
+    /// The last header both tips have in common.
+    pub fork_point: Hash,
+    /// The headers removed from the active chain, old tip first.
+    pub disconnected: Vec<Hash>,
+    /// The headers added to the active chain, the one after the fork point first.
+    pub connected: Vec<Hash>,
+}
+
+/// # HeaderTree
+///
+/// All known headers with their cumulative chainwork, starting at a genesis header.
+///
+/// The active chain ends at the tip with the most chainwork.
+/// On equal chainwork the tip seen first stays active, the same as Bitcoin Core.
+/// Miners have to build on [`HeaderTree::best_tip`].
+///
+/// See [Chainwork](https://learnmeabitcoin.com/technical/blockchain/chainwork/).
+#[derive(Debug, Clone)]
+pub struct HeaderTree {
+    entries: HashMap<Hash, HeaderEntry>,
+    /// The hashes of the active chain by height.
+    active_chain: Vec<Hash>,
+}
+
+impl TipChange {
+    pub fn is_reorg(&self) -> bool {
+        !self.disconnected.is_empty()
+    }
+}
+
+impl HeaderTree {
+    pub fn new(genesis: BlockHeader) -> Self {
+        let hash = genesis.hash();
+        let entry = HeaderEntry {
+            chainwork: genesis.work(),
+            header: genesis,
+            hash,
+            height: 0,
+        };
+
+        Self {
+            entries: HashMap::from([(hash, entry)]),
+            active_chain: vec![hash],
+        }
+    }
+
+    pub fn from_chain_params(params: &ChainParams) -> Self {
+        Self::new(params.genesis_block().header().clone())
+    }
+
+    /// Insert a header whose previous header is known.
+    ///
+    /// The header is not validated, see [`Self::validate_header`].
+    /// Returns the change of the most-work tip, if any.
+    /// Inserting a known header does nothing.
+    pub fn insert(&mut self, header: BlockHeader) -> Result<Option<TipChange>, Error> {
+        let hash = header.hash();
+        self.insert_hashed(header, hash)
+    }
+
+    /// [`Self::insert`] with the already known hash of `header`.
+    pub(crate) fn insert_hashed(
+        &mut self,
+        header: BlockHeader,
+        hash: Hash,
+    ) -> Result<Option<TipChange>, Error> {
+        if self.entries.contains_key(&hash) {
+            return Ok(None);
+        }
+
+        let prev = self
+            .entries
+            .get(&header.prev_block_header_hash)
+            .ok_or(Error::UnknownPrevHeader(header.prev_block_header_hash))?;
+        let entry = HeaderEntry {
+            height: prev.height + 1,
+            chainwork: prev.chainwork + header.work(),
+            header,
+            hash,
+        };
+        let is_best = entry.chainwork > self.best_tip().chainwork;
+        self.entries.insert(hash, entry);
+
+        if is_best {
+            Ok(Some(self.set_tip(hash)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Validate a header before inserting it:
+    /// the previous header has to be known,
+    /// the target has to be [`ChainParams::next_work_required`]
+    /// and [`BlockHeader::validate`] has to pass.
+    pub fn validate_header(
+        &self,
+        header: &BlockHeader,
+        params: &ChainParams,
+        now: u32,
+    ) -> Result<(), Error> {
+        let prev = self
+            .get(&header.prev_block_header_hash)
+            .ok_or(Error::UnknownPrevHeader(header.prev_block_header_hash))?;
+
+        let expected = params.next_work_required_from_ancestors(
+            self.ancestors(&prev.hash).map(|entry| &entry.header),
+            prev.height,
+            header.time,
+        )?;
+        if header.target != expected {
+            return Err(Error::UnexpectedTarget {
+                expected,
+                actual: header.target,
+            });
+        }
+
+        let prev_headers = self.last_headers(&prev.hash, MEDIAN_TIME_SPAN);
+        header.validate(&params.pow_limit, &prev_headers, now)
+    }
+
+    /// The tip of the active chain.
+    pub fn best_tip(&self) -> &HeaderEntry {
+        let hash = self.active_chain[self.active_chain.len() - 1];
+
+        &self.entries[&hash]
+    }
+
+    /// The height of the active chain.
+    pub fn height(&self) -> u32 {
+        (self.active_chain.len() - 1) as u32
+    }
+
+    pub fn get(&self, hash: &Hash) -> Option<&HeaderEntry> {
+        self.entries.get(hash)
+    }
+
+    /// The header at `height` on the active chain.
+    pub fn get_by_height(&self, height: u32) -> Option<&HeaderEntry> {
+        self.active_chain
+            .get(height as usize)
+            .map(|hash| &self.entries[hash])
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.entries.contains_key(hash)
+    }
+
+    /// Whether `hash` is on the active chain.
+    pub fn is_active(&self, hash: &Hash) -> bool {
+        self.get(hash)
+            .is_some_and(|entry| self.active_chain.get(entry.height as usize) == Some(hash))
+    }
+
+    /// The count of all known headers, including the ones not on the active chain.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Up to `count` headers ending at `hash` in chain order, oldest first.
+    pub fn last_headers(&self, hash: &Hash, count: usize) -> Vec<BlockHeader> {
+        let mut headers = self
+            .ancestors(hash)
+            .take(count)
+            .map(|entry| entry.header.clone())
+            .collect::<Vec<_>>();
+        headers.reverse();
+
+        headers
+    }
+
+    /// `hash` and all its ancestors, newest first.
+    pub fn ancestors<'a>(&'a self, hash: &Hash) -> impl Iterator<Item = &'a HeaderEntry> {
+        std::iter::successors(self.get(hash), |entry| {
+            if entry.height == 0 {
+                None
+            } else {
+                self.get(&entry.header.prev_block_header_hash)
+            }
+        })
+    }
+
+    /// The ancestor of `hash` at `height`.
+    pub fn ancestor(&self, hash: &Hash, height: u32) -> Option<&HeaderEntry> {
+        let mut entry = self.get(hash)?;
+        if height > entry.height {
+            return None;
+        }
+
+        // Walk back to the active chain, where heights are indexed.
+        while !self.is_active(&entry.hash) {
+            if entry.height == height {
+                return Some(entry);
+            }
+            entry = self.get(&entry.header.prev_block_header_hash)?;
+        }
+
+        self.get_by_height(height)
+    }
+
+    /// The block locator of the active chain, see [`Self::locator_of`].
+    pub fn locator(&self) -> Vec<Hash> {
+        self.locator_of(&self.best_tip().hash)
+    }
+
+    /// # Block locator
+    ///
+    /// Hashes of `hash` and its ancestors, for a peer to find the last header we have in common.
+    /// The first 11 hashes are the last headers, then the distance doubles each time,
+    /// the last hash is always genesis.
+    ///
+    /// ```plaintext
+    /// tip, tip-1, ..., tip-10, tip-11, tip-13, tip-17, tip-25, ..., genesis
+    /// ```
+    ///
+    /// Empty if `hash` is unknown.
+    /// See Bitcoin Core´s [`LocatorEntries`](https://github.com/bitcoin/bitcoin/blob/master/src/chain.cpp).
+    pub fn locator_of(&self, hash: &Hash) -> Vec<Hash> {
+        let mut locator = Vec::new();
+        let Some(mut entry) = self.get(hash) else {
+            return locator;
+        };
+        let mut step = 1;
+
+        loop {
+            locator.push(entry.hash);
+            if entry.height == 0 {
+                return locator;
+            }
+
+            let height = entry.height.saturating_sub(step);
+            entry = self
+                .ancestor(&entry.hash, height)
+                .expect("All headers descend from genesis.");
+            if locator.len() > 10 {
+                step *= 2;
+            }
+        }
+    }
+
+    /// The last common ancestor of `a` and `b`.
+    pub fn fork_point(&self, a: &Hash, b: &Hash) -> Option<&HeaderEntry> {
+        let mut a = self.get(a)?;
+        let mut b = self.get(b)?;
+
+        while a.height > b.height {
+            a = self.get(&a.header.prev_block_header_hash)?;
+        }
+        while b.height > a.height {
+            b = self.get(&b.header.prev_block_header_hash)?;
+        }
+        while a.hash != b.hash {
+            a = self.get(&a.header.prev_block_header_hash)?;
+            b = self.get(&b.header.prev_block_header_hash)?;
+        }
+
+        Some(a)
+    }
+
+    /// Undo the insertion of `hashes`, `tip` becomes the best tip again.
+    ///
+    /// `tip` has to be the best tip from before `hashes` were inserted.
+    pub(crate) fn remove_inserted(&mut self, hashes: &[Hash], tip: Hash) {
+        self.set_tip(tip);
+        for hash in hashes {
+            self.entries.remove(hash);
+        }
+    }
+
+    fn set_tip(&mut self, tip: Hash) -> TipChange {
+        let old_tip = self.best_tip().hash;
+        let fork_point = self
+            .fork_point(&old_tip, &tip)
+            .expect("All headers descend from genesis.");
+        let (fork_hash, fork_height) = (fork_point.hash, fork_point.height);
+
+        let disconnected = self.active_chain[fork_height as usize + 1..]
+            .iter()
+            .rev()
+            .copied()
+            .collect();
+        let mut connected = self
+            .ancestors(&tip)
+            .take_while(|entry| entry.hash != fork_hash)
+            .map(|entry| entry.hash)
+            .collect::<Vec<_>>();
+        connected.reverse();
+
+        self.active_chain.truncate(fork_height as usize + 1);
+        self.active_chain.extend_from_slice(&connected);
+
+        TipChange {
+            fork_point: fork_hash,
+            disconnected,
+            connected,
+        }
+    }
+}
+
+#[cfg(test)]
+mod header_tree_test {
+    use crate::{
+        block::{BlockHeader, error::Error},
+        chain_params::ChainParams,
+        hash::Hash,
+        test_util::{REGTEST_TARGET, mine_chain, mine_header},
+    };
+
+    use super::HeaderTree;
+
+    #[test]
+    fn extend_and_reorg() {
+        let params = &ChainParams::REGTEST;
+        let mut tree = HeaderTree::from_chain_params(params);
+        let genesis = tree.best_tip().header.clone();
+        assert_eq!(params.genesis_hash(), tree.best_tip().hash);
+
+        let main = mine_chain(&genesis, 3, 1_700_000_000);
+        for header in &main {
+            let change = tree.insert(header.clone()).unwrap().unwrap();
+            assert!(!change.is_reorg());
+            assert_eq!(vec![header.hash()], change.connected);
+        }
+        assert_eq!(3, tree.height());
+        assert_eq!(main[2].hash(), tree.best_tip().hash);
+        assert_eq!(main[1].hash(), tree.get_by_height(2).unwrap().hash);
+        assert_eq!(
+            genesis.work() + genesis.work() * 3.into(),
+            tree.best_tip().chainwork
+        );
+
+        // A fork from height 1 with the same work as the active chain does not replace it.
+        let fork = mine_chain(&main[0], 3, 1_700_001_000);
+        assert_eq!(None, tree.insert(fork[0].clone()).unwrap());
+        assert_eq!(None, tree.insert(fork[1].clone()).unwrap());
+        assert_eq!(main[2].hash(), tree.best_tip().hash);
+        assert!(!tree.is_active(&fork[1].hash()));
+
+        let change = tree.insert(fork[2].clone()).unwrap().unwrap();
+        assert!(change.is_reorg());
+        assert_eq!(main[0].hash(), change.fork_point);
+        assert_eq!(vec![main[2].hash(), main[1].hash()], change.disconnected);
+        assert_eq!(
+            fork.iter().map(BlockHeader::hash).collect::<Vec<_>>(),
+            change.connected
+        );
+        assert_eq!(4, tree.height());
+        assert_eq!(fork[0].hash(), tree.get_by_height(2).unwrap().hash);
+        assert!(tree.is_active(&fork[1].hash()));
+        assert!(!tree.is_active(&main[1].hash()));
+        assert_eq!(7, tree.len());
+
+        assert_eq!(
+            main[0].hash(),
+            tree.fork_point(&main[2].hash(), &fork[2].hash())
+                .unwrap()
+                .hash
+        );
+        assert_eq!(None, tree.insert(main[2].clone()).unwrap());
+
+        // Undoing the fork restores the previous tip.
+        let fork_hashes = fork.iter().map(BlockHeader::hash).collect::<Vec<_>>();
+        tree.remove_inserted(&fork_hashes, main[2].hash());
+        assert_eq!(main[2].hash(), tree.best_tip().hash);
+        assert_eq!(3, tree.height());
+        assert!(tree.is_active(&main[1].hash()));
+        assert!(!tree.contains(&fork[0].hash()));
+        assert_eq!(4, tree.len());
+    }
+
+    #[test]
+    fn most_work_wins_over_length() {
+        let mut tree = HeaderTree::new(BlockHeader {
+            version: 1,
+            prev_block_header_hash: Hash::ZERO,
+            merkle_root_hash: Hash::ZERO,
+            time: 0,
+            target: 0x1D00_FFFF,
+            nonce: 0,
+        });
+        let genesis = tree.best_tip().header.clone();
+
+        // Proof of work is not checked on insert.
+        let easy = (0..3).fold(vec![genesis.clone()], |mut chain, i| {
+            chain.push(BlockHeader {
+                prev_block_header_hash: chain.last().unwrap().hash(),
+                time: i,
+                ..genesis.clone()
+            });
+            chain
+        });
+        for header in &easy[1..] {
+            tree.insert(header.clone()).unwrap();
+        }
+
+        let hard = BlockHeader {
+            prev_block_header_hash: genesis.hash(),
+            target: 0x1C00_FFFF,
+            time: 100,
+            ..genesis.clone()
+        };
+        let change = tree.insert(hard.clone()).unwrap().unwrap();
+
+        assert_eq!(hard.hash(), tree.best_tip().hash);
+        assert_eq!(1, tree.height());
+        assert_eq!(3, change.disconnected.len());
+    }
+
+    #[test]
+    fn unknown_prev_header() {
+        let mut tree = HeaderTree::from_chain_params(&ChainParams::REGTEST);
+        let orphan = BlockHeader {
+            prev_block_header_hash: Hash::from_natural_bytes([1; 32]),
+            ..tree.best_tip().header.clone()
+        };
+
+        assert!(matches!(
+            tree.insert(orphan),
+            Err(Error::UnknownPrevHeader(_))
+        ));
+    }
+
+    #[test]
+    fn validate_header() {
+        let params = &ChainParams::REGTEST;
+        let mut tree = HeaderTree::from_chain_params(params);
+        let genesis = tree.best_tip().header.clone();
+        let now = 1_700_000_000;
+
+        let header = mine_header(&genesis, now, REGTEST_TARGET);
+        assert!(tree.validate_header(&header, params, now).is_ok());
+        tree.insert(header.clone()).unwrap();
+
+        let wrong_target = mine_header(&header, now, 0x2000_FFFF);
+        assert!(matches!(
+            tree.validate_header(&wrong_target, params, now),
+            Err(Error::UnexpectedTarget {
+                expected: 0x207F_FFFF,
+                actual: 0x2000_FFFF
+            })
+        ));
+
+        let too_old = mine_header(&header, genesis.time, REGTEST_TARGET);
+        assert!(matches!(
+            tree.validate_header(&too_old, params, now),
+            Err(Error::TimeTooOld { .. })
+        ));
+    }
+
+    #[test]
+    fn locator() {
+        let params = &ChainParams::REGTEST;
+        let mut tree = HeaderTree::from_chain_params(params);
+        let genesis = tree.best_tip().header.clone();
+        assert_eq!(vec![params.genesis_hash()], tree.locator());
+
+        let chain = mine_chain(&genesis, 30, genesis.time + 1);
+        for header in &chain {
+            tree.insert(header.clone()).unwrap();
+        }
+
+        let heights = tree
+            .locator()
+            .iter()
+            .map(|hash| tree.get(hash).unwrap().height)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![30, 29, 28, 27, 26, 25, 24, 23, 22, 21, 20, 19, 17, 13, 5, 0],
+            heights
+        );
+
+        // Off the active chain.
+        let fork = mine_chain(&chain[19], 3, genesis.time + 100);
+        for header in &fork {
+            tree.insert(header.clone()).unwrap();
+        }
+        let fork_tip = fork[2].hash();
+        assert!(!tree.is_active(&fork_tip));
+        assert_eq!(
+            Some(fork[0].hash()),
+            tree.ancestor(&fork_tip, 21).map(|e| e.hash)
+        );
+        assert_eq!(
+            Some(chain[9].hash()),
+            tree.ancestor(&fork_tip, 10).map(|e| e.hash)
+        );
+        assert!(tree.ancestor(&fork_tip, 24).is_none());
+
+        let locator = tree.locator_of(&fork_tip);
+        assert_eq!(fork_tip, locator[0]);
+        assert_eq!(chain[19].hash(), locator[3]);
+        assert_eq!(Some(&params.genesis_hash()), locator.last());
+        assert!(tree.locator_of(&Hash::ZERO).is_empty());
+    }
+}
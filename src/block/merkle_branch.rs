@@ -0,0 +1,148 @@
+use crate::{
+    block::Block,
+    hash::{Hash, error::Error},
+};
+
+/// The sibling hashes on the path from the coinbase TXID up to the merkle root.
+///
+/// The coinbase is always the leftmost leaf, so each sibling is hashed on the right side.
+/// With the branch, the merkle root for a changed coinbase (e.g. a new extranonce)
+/// is recomputed with one hash per tree level instead of rebuilding the whole tree.
+///
+/// This is the same shape as the `merkle_branch` of a Stratum `mining.notify`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct MerkleBranch(Vec<Hash>);
+
+impl MerkleBranch {
+    pub fn new(hashes: Vec<Hash>) -> Self {
+        Self(hashes)
+    }
+
+    /// Compute the branch of the first TXID (the coinbase) from all TXIDs of a block in order.
+    pub fn from_txids(txids: &[Hash]) -> Self {
+        let mut branch = Vec::new();
+        let mut hashes = txids.to_vec();
+
+        while hashes.len() > 1 {
+            branch.push(hashes[1]);
+
+            if hashes.len() % 2 == 1 {
+                hashes.push(hashes[hashes.len() - 1]);
+            }
+            for i in 0..hashes.len() / 2 {
+                hashes[i] = Block::compute_merkle_branch(&hashes[2 * i], &hashes[2 * i + 1]);
+            }
+            hashes.truncate(hashes.len() / 2);
+        }
+
+        Self(branch)
+    }
+
+    /// Compute the merkle root for `coinbase_txid`.
+    pub fn compute_merkle_root(&self, coinbase_txid: &Hash) -> Hash {
+        self.0.iter().fold(*coinbase_txid, |hash, sibling| {
+            Block::compute_merkle_branch(&hash, sibling)
+        })
+    }
+
+    pub fn hashes(&self) -> &[Hash] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Parse a Stratum `merkle_branch`.
+    ///
+    /// Stratum sends the hashes as hex in natural byte order.
+    pub fn from_stratum<S: AsRef<str>>(branch: &[S]) -> Result<Self, Error> {
+        branch
+            .iter()
+            .map(|hash| Hash::from_natural_hex(hash.as_ref()))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self)
+    }
+
+    /// Serialize into a Stratum `merkle_branch`.
+    pub fn to_stratum(&self) -> Vec<String> {
+        self.0
+            .iter()
+            .map(|hash| hex::encode(hash.as_bytes()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod merkle_branch_test {
+    use crate::{block::Block, hash::Hash};
+
+    use super::MerkleBranch;
+
+    fn txids(count: usize) -> Vec<Hash> {
+        (0..count)
+            .map(|i| {
+                let mut bytes = [0xAAu8; 32];
+                bytes[..8].copy_from_slice(&(i as u64).to_le_bytes());
+                Hash::from_natural_bytes(bytes)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn block_100000() {
+        let txids = [
+            "8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+            "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+            "6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+            "e9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d",
+        ]
+        .map(|hash| hash.parse::<Hash>().unwrap());
+        let branch = MerkleBranch::from_txids(&txids);
+
+        assert_eq!(2, branch.len());
+        assert_eq!(txids[1], branch.hashes()[0]);
+        assert_eq!(
+            "f3e94742aca4b5ef85488dc37c06c3282295ffec960994b2c0d5ac2a25a95766",
+            branch.compute_merkle_root(&txids[0]).to_string()
+        );
+    }
+
+    #[test]
+    fn matches_full_tree() {
+        for count in [1usize, 2, 3, 5, 8, 13, 100] {
+            let mut txids = txids(count);
+            let branch = MerkleBranch::from_txids(&txids);
+
+            assert_eq!(
+                Block::compute_merkle_root(txids.clone()).0,
+                branch.compute_merkle_root(&txids[0]),
+                "{} transactions",
+                count
+            );
+
+            // A new coinbase does not change the branch.
+            txids[0] = Hash::from_natural_bytes([0x55; 32]);
+            assert_eq!(
+                Block::compute_merkle_root(txids.clone()).0,
+                branch.compute_merkle_root(&txids[0]),
+                "{} transactions with new coinbase",
+                count
+            );
+        }
+    }
+
+    #[test]
+    fn stratum() {
+        let branch = MerkleBranch::from_txids(&txids(6));
+        let stratum = branch.to_stratum();
+
+        assert_eq!(hex::encode([0xAA; 24]), stratum[0][16..]);
+        assert_eq!(branch, MerkleBranch::from_stratum(&stratum).unwrap());
+        assert!(MerkleBranch::from_stratum(&["00"]).is_err());
+    }
+}
@@ -1,33 +1,524 @@
+use std::io::{self, Read, Write};
+
 use super::error::Error;
+use crate::{
+    encoding::{self, Decodable, Encodable, read_u8},
+    hash::{Hash, Hash256},
+};
+
+/// # Transaction
+///
+/// A Bitcoin transaction in either legacy or [BIP144](https://github.com/bitcoin/bips/blob/master/bip-0144.mediawiki) segwit serialization.
+///
+/// An example legacy transaction in hex:
+/// ```plaintext
+/// 01000000 ........................... Version: 1
+/// 01 ................................. Input count
+/// | c997a5e56e104102fa209c6a852dd906
+/// | 60a20b2d9c352423edce25857fcd3704 . Outpoint TXID
+/// | 00000000 ......................... Outpoint index
+/// | 48 ............................... scriptSig size
+/// | 4730...01 ........................ scriptSig
+/// | ffffffff ......................... Sequence
+/// 02 ................................. Output count
+/// | 00ca9a3b00000000 ................. Value: 10 BTC
+/// | 43 ............................... scriptPubKey size
+/// | 4104...ac ........................ scriptPubKey
+/// | ...
+/// 00000000 ........................... Locktime
+/// ```
+///
+/// Segwit transactions have a marker (`00`) and a flag (`01`) after the version
+/// and the witness stacks of all inputs before the locktime.
+///
+/// See [Transaction](https://learnmeabitcoin.com/technical/transaction/).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Transaction {
+    /// > little-endian
+    pub version: i32,
+    pub inputs: Vec<Input>,
+    pub outputs: Vec<Output>,
+    /// The block height or Unix time until which this transaction can not be mined.
+    ///
+    /// > little-endian
+    pub lock_time: u32,
+}
+
+/// A reference to an output of a previous transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OutPoint {
+    /// > internal byte order
+    pub txid: Hash,
+    /// The index of the output.
+    ///
+    /// > little-endian
+    pub vout: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Input {
+    pub previous_output: OutPoint,
+    pub script_sig: Vec<u8>,
+    /// > little-endian
+    pub sequence: u32,
+    /// The witness stack. Empty for non-segwit inputs.
+    pub witness: Vec<Vec<u8>>,
+}
 
-#[derive(Debug, Clone)]
-pub struct Transaction {}
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Output {
+    /// The amount in satoshis.
+    ///
+    /// > little-endian
+    pub value: u64,
+    pub script_pubkey: Vec<u8>,
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct RawTransaction {
     pub data: Vec<u8>,
 }
 
-impl Transaction {}
+impl Transaction {
+    const SEGWIT_MARKER: u8 = 0x00;
+    const SEGWIT_FLAG: u8 = 0x01;
+
+    /// Parse a transaction in legacy or segwit serialization.
+    ///
+    /// All bytes have to be consumed.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut cursor = bytes;
+        let transaction = Self::consensus_decode(&mut cursor)
+            .map_err(|e| Error::from_decode(e, bytes.len() - cursor.len()))?;
+
+        if cursor.is_empty() {
+            Ok(transaction)
+        } else {
+            Err(Error::TrailingBytes {
+                offset: bytes.len() - cursor.len(),
+                count: cursor.len(),
+            })
+        }
+    }
+
+    /// Serialize the transaction.
+    ///
+    /// Uses the segwit serialization if any input has a witness.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_consensus_bytes()
+    }
+
+    /// Serialize the transaction without marker, flag and witnesses.
+    ///
+    /// This is the serialization the TXID is computed from.
+    pub fn to_bytes_without_witness(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.encode(&mut bytes, false)
+            .expect("Writing into a Vec never fails.");
+
+        bytes
+    }
+
+    fn encode<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        with_witness: bool,
+    ) -> Result<usize, io::Error> {
+        let mut len = self.version.consensus_encode(writer)?;
+        if with_witness {
+            len += [Self::SEGWIT_MARKER, Self::SEGWIT_FLAG].consensus_encode(writer)?;
+        }
+        len += self.inputs.consensus_encode(writer)?;
+        len += self.outputs.consensus_encode(writer)?;
+        if with_witness {
+            for input in &self.inputs {
+                len += input.witness.consensus_encode(writer)?;
+            }
+        }
+        len += self.lock_time.consensus_encode(writer)?;
+
+        Ok(len)
+    }
+
+    /// Whether any input has a witness.
+    pub fn has_witness(&self) -> bool {
+        self.inputs.iter().any(|input| !input.witness.is_empty())
+    }
+
+    /// Whether this is a coinbase transaction.
+    ///
+    /// A coinbase has exactly one input which spends the null outpoint.
+    pub fn is_coinbase(&self) -> bool {
+        self.inputs.len() == 1 && self.inputs[0].previous_output.is_null()
+    }
+
+    /// The TXID of this transaction.
+    ///
+    /// The Hash256 of the serialization without witness data.
+    pub fn txid(&self) -> Hash {
+        Hash256::digest(&self.to_bytes_without_witness())
+    }
+
+    /// The WTXID of this transaction.
+    ///
+    /// The Hash256 of the serialization with witness data.
+    /// Equal to the TXID for transactions without witnesses.
+    pub fn wtxid(&self) -> Hash {
+        Hash256::digest(&self.to_bytes())
+    }
+
+    /// The sum of all output values in satoshis.
+    pub fn output_value(&self) -> u64 {
+        self.outputs.iter().map(|output| output.value).sum()
+    }
+}
+
+impl OutPoint {
+    /// The outpoint spent by coinbase transactions.
+    pub const NULL: Self = Self {
+        txid: Hash::ZERO,
+        vout: u32::MAX,
+    };
+
+    pub fn is_null(&self) -> bool {
+        self == &Self::NULL
+    }
+}
 
 impl RawTransaction {
     pub fn new(data: Vec<u8>) -> Self {
         Self { data }
     }
+
+    /// Whether the data starts with the segwit marker and a non-zero flag.
+    pub fn is_segwit(&self) -> bool {
+        self.data.len() > 5 && self.data[4] == Transaction::SEGWIT_MARKER && self.data[5] != 0
+    }
+
+    /// The TXID of this transaction.
+    ///
+    /// Segwit transactions are parsed to strip the witness data.
+    pub fn txid(&self) -> Result<Hash, Error> {
+        if self.is_segwit() {
+            Ok(Transaction::from_bytes(&self.data)?.txid())
+        } else {
+            Ok(Hash256::digest(&self.data))
+        }
+    }
+
+    /// The WTXID of this transaction.
+    ///
+    /// The Hash256 of the raw transaction data.
+    pub fn wtxid(&self) -> Hash {
+        Hash256::digest(&self.data)
+    }
 }
 
 impl TryFrom<RawTransaction> for Transaction {
     type Error = Error;
 
     fn try_from(raw_transaction: RawTransaction) -> Result<Self, Self::Error> {
-        todo!()
+        Self::from_bytes(&raw_transaction.data)
     }
 }
 
-impl TryFrom<Transaction> for RawTransaction {
-    type Error = Error;
+impl From<Transaction> for RawTransaction {
+    fn from(transaction: Transaction) -> Self {
+        Self::new(transaction.to_bytes())
+    }
+}
+
+impl Encodable for Transaction {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        self.encode(writer, self.has_witness())
+    }
+}
+
+impl Decodable for Transaction {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, encoding::error::Error> {
+        let version = i32::consensus_decode(reader)?;
+
+        let mut flag = 0;
+        let mut inputs = Vec::<Input>::consensus_decode(reader)?;
+        let mut outputs = Vec::new();
+        if inputs.is_empty() {
+            // Either the segwit marker or a transaction without inputs and outputs,
+            // like Bitcoin Core the byte after an empty input list is the flag.
+            flag = read_u8(reader)?;
+            if flag != 0 {
+                if flag != Self::SEGWIT_FLAG {
+                    return Err(encoding::error::Error::InvalidSegwitFlag(flag));
+                }
+                inputs = Vec::consensus_decode(reader)?;
+                outputs = Vec::consensus_decode(reader)?;
+            }
+        } else {
+            outputs = Vec::consensus_decode(reader)?;
+        }
+
+        if flag == Self::SEGWIT_FLAG {
+            for input in inputs.iter_mut() {
+                input.witness = Vec::consensus_decode(reader)?;
+            }
+
+            if inputs.iter().all(|input| input.witness.is_empty()) {
+                return Err(encoding::error::Error::SuperfluousWitness);
+            }
+        }
+
+        let lock_time = u32::consensus_decode(reader)?;
+
+        Ok(Self {
+            version,
+            inputs,
+            outputs,
+            lock_time,
+        })
+    }
+}
+
+impl Encodable for OutPoint {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        Ok(self.txid.consensus_encode(writer)? + self.vout.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for OutPoint {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, encoding::error::Error> {
+        Ok(Self {
+            txid: Hash::consensus_decode(reader)?,
+            vout: u32::consensus_decode(reader)?,
+        })
+    }
+}
+
+/// The witness is not part of the input serialization.
+/// It is encoded by the [`Transaction`] after all outputs.
+impl Encodable for Input {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        Ok(self.previous_output.consensus_encode(writer)?
+            + self.script_sig.consensus_encode(writer)?
+            + self.sequence.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for Input {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, encoding::error::Error> {
+        Ok(Self {
+            previous_output: OutPoint::consensus_decode(reader)?,
+            script_sig: Vec::consensus_decode(reader)?,
+            sequence: u32::consensus_decode(reader)?,
+            witness: Vec::new(),
+        })
+    }
+}
+
+impl Encodable for Output {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        Ok(self.value.consensus_encode(writer)? + self.script_pubkey.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for Output {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, encoding::error::Error> {
+        Ok(Self {
+            value: u64::consensus_decode(reader)?,
+            script_pubkey: Vec::consensus_decode(reader)?,
+        })
+    }
+}
+
+impl Encodable for RawTransaction {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        writer.write_all(&self.data)?;
+
+        Ok(self.data.len())
+    }
+}
+
+/// The transaction is parsed to find its end, the data are the bytes read.
+impl Decodable for RawTransaction {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, encoding::error::Error> {
+        let mut recorder = Recorder {
+            reader,
+            data: Vec::new(),
+        };
+        Transaction::consensus_decode(&mut recorder)?;
+
+        Ok(Self::new(recorder.data))
+    }
+}
+
+/// Keeps a copy of everything read from `reader`.
+struct Recorder<'a, R: ?Sized> {
+    reader: &'a mut R,
+    data: Vec<u8>,
+}
+
+impl<R: Read + ?Sized> Read for Recorder<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.reader.read(buf)?;
+        self.data.extend_from_slice(&buf[..len]);
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod transaction_test {
+    use crate::{block::error::Error, encoding::Decodable, hash::Hash};
+
+    use super::{Input, OutPoint, Output, RawTransaction, Transaction};
+
+    /// The coinbase transaction of the genesis block.
+    const GENESIS_COINBASE: &str = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000";
+    /// The first transaction between two people, included in block 170.
+    const BLOCK_170_TRANSACTION: &str = "0100000001c997a5e56e104102fa209c6a852dd90660a20b2d9c352423edce25857fcd3704000000004847304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901ffffffff0200ca9a3b00000000434104ae1a62fe09c5f51b13905f07f06b99a2f7159b2225f374cd378d71302fa28414e7aab37397f554a7df5f142c21c1b7303b8a0626f1baded5c72a704f7e6cd84cac00286bee0000000043410411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3ac00000000";
+
+    #[test]
+    fn genesis_coinbase() {
+        let bytes = hex::decode(GENESIS_COINBASE).unwrap();
+        let transaction = Transaction::from_bytes(&bytes).unwrap();
+
+        assert!(transaction.is_coinbase());
+        assert!(!transaction.has_witness());
+        assert_eq!(1, transaction.version);
+        assert_eq!(5_000_000_000, transaction.output_value());
+        assert_eq!(bytes, transaction.to_bytes());
+        assert_eq!(
+            "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+            transaction.txid().to_string()
+        );
+        assert_eq!(transaction.txid(), transaction.wtxid());
+    }
+
+    #[test]
+    fn block_170_transaction() {
+        let raw_transaction = RawTransaction::new(hex::decode(BLOCK_170_TRANSACTION).unwrap());
+        let transaction = Transaction::try_from(raw_transaction.clone()).unwrap();
+
+        assert!(!transaction.is_coinbase());
+        assert_eq!(1, transaction.inputs.len());
+        assert_eq!(
+            "0437cd7f8525ceed2324359c2d0ba26006d92d856a9c20fa0241106ee5a597c9",
+            transaction.inputs[0].previous_output.txid.to_string()
+        );
+        assert_eq!(2, transaction.outputs.len());
+        assert_eq!(1_000_000_000, transaction.outputs[0].value);
+        assert_eq!(4_000_000_000, transaction.outputs[1].value);
+        assert_eq!(
+            "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16",
+            transaction.txid().to_string()
+        );
+        assert_eq!(raw_transaction.txid().unwrap(), transaction.txid());
+        assert_eq!(raw_transaction.data, RawTransaction::from(transaction).data);
+    }
+
+    #[test]
+    fn without_inputs_and_outputs() {
+        // Version, no inputs, flag 0, locktime.
+        let bytes = hex::decode("02000000000011223344").unwrap();
+
+        let transaction = Transaction::from_bytes(&bytes).unwrap();
+        assert!(transaction.inputs.is_empty());
+        assert!(transaction.outputs.is_empty());
+        assert_eq!(0x4433_2211, transaction.lock_time);
+        assert_eq!(bytes, transaction.to_bytes());
+
+        let raw_transaction = RawTransaction::from_consensus_bytes(&bytes).unwrap();
+        assert_eq!(bytes, raw_transaction.data);
+    }
+
+    #[test]
+    fn raw_transaction_keeps_bytes() {
+        let bytes = hex::decode(BLOCK_170_TRANSACTION).unwrap();
+        let mut cursor = &bytes[..];
+
+        let raw_transaction = RawTransaction::consensus_decode(&mut cursor).unwrap();
+        assert!(cursor.is_empty());
+        assert_eq!(bytes, raw_transaction.data);
+    }
+
+    #[test]
+    fn segwit_round_trip() {
+        let transaction = Transaction {
+            version: 2,
+            inputs: vec![Input {
+                previous_output: OutPoint {
+                    txid: Hash::from_natural_bytes([0x11; 32]),
+                    vout: 1,
+                },
+                script_sig: Vec::new(),
+                sequence: 0xFFFF_FFFD,
+                witness: vec![vec![0x30; 71], vec![0x02; 33]],
+            }],
+            outputs: vec![Output {
+                value: 99_000,
+                script_pubkey: [vec![0x00, 0x14], vec![0x22; 20]].concat(),
+            }],
+            lock_time: 840_000,
+        };
+
+        let bytes = transaction.to_bytes();
+        assert_eq!([0x00, 0x01], bytes[4..6]);
+
+        let raw_transaction = RawTransaction::new(bytes.clone());
+        assert!(raw_transaction.is_segwit());
+
+        let parsed = Transaction::from_bytes(&bytes).unwrap();
+        assert_eq!(transaction, parsed);
+        assert_eq!(bytes, parsed.to_bytes());
+        assert_ne!(parsed.txid(), parsed.wtxid());
+        assert_eq!(raw_transaction.txid().unwrap(), parsed.txid());
+        assert_eq!(raw_transaction.wtxid(), parsed.wtxid());
+
+        let legacy = Transaction::from_bytes(&transaction.to_bytes_without_witness()).unwrap();
+        assert!(!legacy.has_witness());
+        assert_eq!(parsed.txid(), legacy.txid());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let bytes = hex::decode(GENESIS_COINBASE).unwrap();
+
+        assert!(matches!(
+            Transaction::from_bytes(&bytes[..bytes.len() - 2]),
+            Err(Error::UnexpectedEnd {
+                offset: 202,
+                needed: 2
+            })
+        ));
+
+        let mut trailing = bytes.clone();
+        trailing.push(0x00);
+        assert!(matches!(
+            Transaction::from_bytes(&trailing),
+            Err(Error::TrailingBytes {
+                offset: 204,
+                count: 1
+            })
+        ));
+
+        let mut invalid_flag = bytes.clone();
+        invalid_flag.splice(4..4, [0x00, 0x02]);
+        assert!(matches!(
+            Transaction::from_bytes(&invalid_flag),
+            Err(Error::InvalidSegwitFlag { offset: 6, flag: 2 })
+        ));
+
+        let mut superfluous_witness = bytes.clone();
+        superfluous_witness.splice(4..4, [0x00, 0x01]);
+        superfluous_witness.splice(superfluous_witness.len() - 4.., [0x00, 0, 0, 0, 0]);
+        assert!(matches!(
+            Transaction::from_bytes(&superfluous_witness),
+            Err(Error::SuperfluousWitness { offset: 203 })
+        ));
 
-    fn try_from(transaction: Transaction) -> Result<Self, Self::Error> {
-        todo!()
+        let mut non_canonical = bytes;
+        non_canonical.splice(4..5, [0xFD, 0x01, 0x00]);
+        assert!(matches!(
+            Transaction::from_bytes(&non_canonical),
+            Err(Error::NonCanonicalCompactSize { offset: 7 })
+        ));
     }
 }
@@ -0,0 +1,202 @@
+use crate::block::error::Error;
+
+/// The version bits BIP320 reserves for general purpose use: bits 13 to 28.
+pub const DEFAULT_VERSION_ROLLING_MASK: u32 = 0x1FFF_E000;
+
+/// # VersionRolling
+///
+/// Rolls the version bits allowed by a mask as an extra search dimension next to the nonce.
+/// With the default mask this multiplies the search space by 65536 without changing the coinbase.
+///
+/// The mask is negotiated with the pool (Stratum `mining.configure`).
+/// Bits outside the mask always keep the value of the job version.
+///
+/// ```plaintext
+/// 20000000 ........................... Job version
+/// 1fffe000 ........................... Mask
+/// 2000e000 ........................... A rolled version
+/// ```
+///
+/// See [BIP320](https://github.com/bitcoin/bips/blob/master/bip-0320.mediawiki).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionRolling {
+    job_version: i32,
+    mask: u32,
+}
+
+/// Iterates all versions of a [`VersionRolling`], starting with all masked bits unset.
+#[derive(Debug, Clone)]
+pub struct Versions {
+    rolling: VersionRolling,
+    next_bits: Option<u32>,
+}
+
+impl VersionRolling {
+    pub fn new(job_version: i32, mask: u32) -> Self {
+        Self { job_version, mask }
+    }
+
+    /// Use [`DEFAULT_VERSION_ROLLING_MASK`].
+    pub fn with_default_mask(job_version: i32) -> Self {
+        Self::new(job_version, DEFAULT_VERSION_ROLLING_MASK)
+    }
+
+    pub fn job_version(&self) -> i32 {
+        self.job_version
+    }
+
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    /// The count of distinct versions: 2 to the power of the set mask bits.
+    pub fn count(&self) -> u64 {
+        1 << self.mask.count_ones()
+    }
+
+    /// The job version with the masked bits replaced by `bits`.
+    ///
+    /// Bits of `bits` outside the mask are ignored.
+    pub fn apply(&self, bits: u32) -> i32 {
+        ((self.job_version as u32 & !self.mask) | (bits & self.mask)) as i32
+    }
+
+    /// The bits of `version` which differ from the job version, as sent in a Stratum `mining.submit`.
+    pub fn rolled_bits(&self, version: i32) -> u32 {
+        (version ^ self.job_version) as u32
+    }
+
+    /// All versions allowed by the mask.
+    pub fn versions(&self) -> Versions {
+        Versions {
+            rolling: *self,
+            next_bits: Some(0),
+        }
+    }
+
+    /// Whether `version` only differs from the job version inside the mask.
+    pub fn is_valid(&self, version: i32) -> bool {
+        self.rolled_bits(version) & !self.mask == 0
+    }
+
+    /// Validate a submitted version, see [`Self::is_valid`].
+    pub fn validate(&self, version: i32) -> Result<(), Error> {
+        if self.is_valid(version) {
+            Ok(())
+        } else {
+            Err(Error::VersionOutsideMask {
+                job_version: self.job_version,
+                version,
+                mask: self.mask,
+            })
+        }
+    }
+}
+
+impl Iterator for Versions {
+    type Item = i32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bits = self.next_bits?;
+        let mask = self.rolling.mask;
+
+        // Count up through the masked bits only; wrapping to zero means all were visited.
+        let next_bits = ((bits | !mask).wrapping_add(1)) & mask;
+        self.next_bits = (next_bits != 0).then_some(next_bits);
+
+        Some(self.rolling.apply(bits))
+    }
+}
+
+#[cfg(test)]
+mod version_rolling_test {
+    use crate::{
+        block::{BlockHeader, error::Error},
+        hash::{Hash256, HeaderHasher},
+    };
+
+    use super::{DEFAULT_VERSION_ROLLING_MASK, VersionRolling};
+
+    #[test]
+    fn versions() {
+        let rolling = VersionRolling::with_default_mask(0x2000_0004);
+        assert_eq!(65_536, rolling.count());
+
+        let versions = rolling.versions().collect::<Vec<_>>();
+        assert_eq!(65_536, versions.len());
+        assert_eq!(0x2000_0004, versions[0]);
+        assert_eq!(0x2000_2004, versions[1]);
+        assert_eq!(0x2000_4004, versions[2]);
+        assert_eq!(0x3FFF_E004, versions[65_535]);
+        assert!(versions.iter().all(|&version| rolling.is_valid(version)));
+
+        let mut sorted = versions.clone();
+        sorted.sort_unstable();
+        sorted.dedup();
+        assert_eq!(versions.len(), sorted.len());
+
+        let sparse = VersionRolling::new(0x2000_0000, 0x0000_A000);
+        assert_eq!(
+            vec![0x2000_0000, 0x2000_2000, 0x2000_8000, 0x2000_A000],
+            sparse.versions().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![0x2000_0000],
+            VersionRolling::new(0x2000_0000, 0)
+                .versions()
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn validate() {
+        let rolling = VersionRolling::with_default_mask(0x2000_0000);
+
+        assert!(rolling.validate(0x2000_0000).is_ok());
+        assert!(rolling.validate(0x3FFF_E000).is_ok());
+        assert_eq!(0x0ABC_0000, rolling.rolled_bits(0x2ABC_0000));
+        assert_eq!(0x3ABC_0000, rolling.apply(0xFABC_1FFF));
+
+        assert!(matches!(
+            rolling.validate(0x6000_0000),
+            Err(Error::VersionOutsideMask {
+                job_version: 0x2000_0000,
+                version: 0x6000_0000,
+                mask: DEFAULT_VERSION_ROLLING_MASK,
+            })
+        ));
+        assert!(rolling.validate(0x2000_0001).is_err());
+    }
+
+    #[test]
+    fn roll_with_header_hasher() {
+        let mut header = BlockHeader {
+            version: 0x2000_0000,
+            prev_block_header_hash: Default::default(),
+            merkle_root_hash: Default::default(),
+            time: 1_700_000_000,
+            target: 0x1703_4219,
+            nonce: 0,
+        };
+        let mut bytes = header.as_bytes();
+        let mut hasher = HeaderHasher::new(&bytes);
+        let rolling = VersionRolling::with_default_mask(header.version);
+
+        for version in rolling.versions().take(4) {
+            hasher.set_version(version);
+            bytes.set_version(version);
+            header.version = version;
+
+            for nonce in [0, 1, u32::MAX] {
+                bytes.set_nonce(nonce);
+                header.nonce = nonce;
+
+                assert_eq!(
+                    Hash256::digest(bytes.as_bytes()),
+                    hasher.hash_with_nonce(nonce)
+                );
+                assert_eq!(header.hash(), hasher.hash_with_nonce(nonce));
+            }
+        }
+    }
+}
@@ -0,0 +1,394 @@
+use crate::{
+    block::{Block, Output, RawTransaction, Transaction, error::Error},
+    hash::{Hash, Hash256},
+};
+
+/// The script prefix of a witness commitment output: `OP_RETURN OP_PUSHBYTES_36 aa21a9ed`.
+pub const WITNESS_COMMITMENT_HEADER: [u8; 6] = [0x6A, 0x24, 0xAA, 0x21, 0xA9, 0xED];
+/// The witness reserved value used by this miner. Any 32 bytes are valid.
+pub const WITNESS_RESERVED_VALUE: [u8; 32] = [0u8; 32];
+
+/// # BIP141 witness commitment
+///
+/// The witness merkle root is computed like the merkle root, but over WTXIDs
+/// and with the coinbase WTXID replaced by zero.
+/// The commitment is `Hash256(witness merkle root || witness reserved value)`,
+/// where the witness reserved value is the only witness item of the coinbase input.
+///
+/// It is stored in a coinbase output:
+/// ```plaintext
+/// 0000000000000000 ................... Value: 0
+/// 26 ................................. scriptPubKey size
+/// 6a ................................. OP_RETURN
+/// 24 ................................. OP_PUSHBYTES_36
+/// aa21a9ed ........................... Commitment header
+/// ...32 bytes... ..................... Commitment
+/// ```
+///
+/// See [BIP141](https://github.com/bitcoin/bips/blob/master/bip-0141.mediawiki#commitment-structure).
+impl Block {
+    /// Computes the witness merkle root over the WTXIDs of `raw_transactions`.
+    ///
+    /// The coinbase transaction has to be the first one; its WTXID is replaced by zero.
+    pub fn compute_witness_merkle_root(raw_transactions: &[RawTransaction]) -> Hash {
+        let wtxids = raw_transactions
+            .iter()
+            .enumerate()
+            .map(|(i, raw_transaction)| {
+                if i == 0 {
+                    Hash::ZERO
+                } else {
+                    raw_transaction.wtxid()
+                }
+            })
+            .collect();
+
+        Self::compute_merkle_root(wtxids).0
+    }
+
+    /// Combine the witness merkle root with the witness reserved value.
+    pub fn compute_witness_commitment(
+        witness_merkle_root: &Hash,
+        witness_reserved_value: &[u8; 32],
+    ) -> Hash {
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(witness_merkle_root.as_bytes());
+        bytes[32..].copy_from_slice(witness_reserved_value);
+
+        Hash256::digest(&bytes)
+    }
+
+    /// Whether any transaction of this block has a witness.
+    pub fn has_witness(&self) -> bool {
+        self.transactions.iter().any(RawTransaction::is_segwit)
+    }
+
+    /// Validate the witness commitment of the coinbase against the transactions,
+    /// the same as Bitcoin Core´s `CheckWitnessMalleation`.
+    ///
+    /// Blocks without a commitment must not contain any witness data.
+    pub fn validate_witness_commitment(&self) -> Result<(), Error> {
+        let coinbase = Transaction::try_from(
+            self.transactions
+                .first()
+                .ok_or(Error::NoTransactions)?
+                .clone(),
+        )?;
+
+        let Some(commitment) = coinbase.witness_commitment() else {
+            return if self.has_witness() {
+                Err(Error::UnexpectedWitness)
+            } else {
+                Ok(())
+            };
+        };
+
+        let witness_reserved_value = coinbase.witness_reserved_value()?;
+        let expected = Self::compute_witness_commitment(
+            &Self::compute_witness_merkle_root(&self.transactions),
+            &witness_reserved_value,
+        );
+
+        if commitment == expected {
+            Ok(())
+        } else {
+            Err(Error::WitnessCommitmentMismatch {
+                expected,
+                actual: commitment,
+            })
+        }
+    }
+
+    /// Recompute the witness commitment, insert it into the coinbase and update the merkle root.
+    ///
+    /// Does nothing if no transaction has a witness.
+    pub fn update_witness_commitment(&mut self) -> Result<(), Error> {
+        if !self
+            .transactions
+            .iter()
+            .skip(1)
+            .any(RawTransaction::is_segwit)
+        {
+            return Ok(());
+        }
+
+        let mut coinbase = Transaction::try_from(
+            self.transactions
+                .first()
+                .ok_or(Error::NoTransactions)?
+                .clone(),
+        )?;
+        let witness_merkle_root = Self::compute_witness_merkle_root(&self.transactions);
+        coinbase.set_witness_commitment(&witness_merkle_root)?;
+
+        self.transactions[0] = coinbase.into();
+        self.update_merkle_root_hash()
+    }
+}
+
+impl Transaction {
+    /// Extract the witness commitment from the outputs.
+    ///
+    /// If multiple outputs match, the last one is used.
+    pub fn witness_commitment(&self) -> Option<Hash> {
+        self.outputs
+            .iter()
+            .rev()
+            .find(|output| Output::is_witness_commitment(output))
+            .map(|output| {
+                let mut commitment = [0u8; 32];
+                commitment.copy_from_slice(&output.script_pubkey[6..38]);
+
+                Hash::from_natural_bytes(commitment)
+            })
+    }
+
+    /// The witness reserved value of a coinbase.
+    ///
+    /// The coinbase input has to have exactly one witness item of 32 bytes.
+    pub fn witness_reserved_value(&self) -> Result<[u8; 32], Error> {
+        match self.inputs.first().map(|input| input.witness.as_slice()) {
+            Some([item]) => item
+                .as_slice()
+                .try_into()
+                .map_err(|_| Error::InvalidWitnessReservedValue),
+            _ => Err(Error::InvalidWitnessReservedValue),
+        }
+    }
+
+    /// Insert the commitment for `witness_merkle_root` into this coinbase.
+    ///
+    /// An existing commitment output is replaced, otherwise one is appended.
+    /// The witness reserved value is set to [`WITNESS_RESERVED_VALUE`] if the coinbase has none.
+    ///
+    /// Errors if this is not a coinbase, see [`Self::is_coinbase`].
+    pub fn set_witness_commitment(&mut self, witness_merkle_root: &Hash) -> Result<(), Error> {
+        if !self.is_coinbase() {
+            return Err(Error::NotCoinbase);
+        }
+
+        let witness_reserved_value = self.witness_reserved_value().unwrap_or_else(|_| {
+            self.inputs[0].witness = vec![WITNESS_RESERVED_VALUE.to_vec()];
+            WITNESS_RESERVED_VALUE
+        });
+        let commitment =
+            Block::compute_witness_commitment(witness_merkle_root, &witness_reserved_value);
+        let output = Output::new_witness_commitment(&commitment);
+
+        match self
+            .outputs
+            .iter_mut()
+            .rev()
+            .find(|output| Output::is_witness_commitment(output))
+        {
+            Some(existing) => *existing = output,
+            None => self.outputs.push(output),
+        }
+
+        Ok(())
+    }
+}
+
+impl Output {
+    pub fn new_witness_commitment(commitment: &Hash) -> Self {
+        Self {
+            value: 0,
+            script_pubkey: [&WITNESS_COMMITMENT_HEADER[..], commitment.as_bytes()].concat(),
+        }
+    }
+
+    /// Whether the scriptPubKey is at least 38 bytes and starts with [`WITNESS_COMMITMENT_HEADER`].
+    pub fn is_witness_commitment(&self) -> bool {
+        self.script_pubkey.len() >= 38 && self.script_pubkey.starts_with(&WITNESS_COMMITMENT_HEADER)
+    }
+}
+
+#[cfg(test)]
+mod witness_test {
+    use crate::{
+        block::{
+            Block, CoinbaseBuilder, Input, OutPoint, Output, RawTransaction, Transaction,
+            error::Error,
+        },
+        chain_params::ChainParams,
+        hash::Hash,
+    };
+
+    const P2WPKH: [u8; 22] = [
+        0x00, 0x14, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33,
+        0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33,
+    ];
+
+    fn segwit_transaction(seed: u8) -> RawTransaction {
+        Transaction {
+            version: 2,
+            inputs: vec![Input {
+                previous_output: OutPoint {
+                    txid: Hash::from_natural_bytes([seed; 32]),
+                    vout: 0,
+                },
+                script_sig: Vec::new(),
+                sequence: u32::MAX,
+                witness: vec![vec![seed; 72], vec![0x02; 33]],
+            }],
+            outputs: vec![Output {
+                value: 10_000,
+                script_pubkey: P2WPKH.to_vec(),
+            }],
+            lock_time: 0,
+        }
+        .into()
+    }
+
+    fn segwit_block() -> Block {
+        let transactions = vec![segwit_transaction(0x44), segwit_transaction(0x55)];
+        let coinbase = CoinbaseBuilder::new(840_000, &ChainParams::MAINNET)
+            .payout(P2WPKH.to_vec(), 312_500_000)
+            .transactions(&transactions)
+            .build()
+            .unwrap();
+
+        Block::new(
+            0x2000_0000,
+            Hash::ZERO,
+            0x1703_4219,
+            [vec![coinbase], transactions].concat(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn witness_merkle_root() {
+        let transactions = [segwit_transaction(0x44), segwit_transaction(0x55)];
+        let root = Block::compute_witness_merkle_root(&transactions);
+
+        // The first WTXID is replaced by zero.
+        assert_eq!(
+            Block::compute_merkle_branch(&Hash::ZERO, &transactions[1].wtxid()),
+            root
+        );
+    }
+
+    #[test]
+    fn validate() {
+        let block = segwit_block();
+        assert!(block.has_witness());
+        assert!(block.validate_witness_commitment().is_ok());
+
+        let coinbase = Transaction::try_from(block.transactions[0].clone()).unwrap();
+        assert_eq!([0u8; 32], coinbase.witness_reserved_value().unwrap());
+        assert!(coinbase.witness_commitment().is_some());
+    }
+
+    #[test]
+    fn validate_mismatch() {
+        let mut block = segwit_block();
+        block.transactions[2] = segwit_transaction(0x66);
+
+        assert!(matches!(
+            block.validate_witness_commitment(),
+            Err(Error::WitnessCommitmentMismatch { .. })
+        ));
+
+        block.update_witness_commitment().unwrap();
+        assert!(block.validate_witness_commitment().is_ok());
+        assert_eq!(
+            Block::compute_merkle_root_hash(&block.transactions).unwrap(),
+            block.header.merkle_root_hash
+        );
+    }
+
+    #[test]
+    fn validate_without_commitment() {
+        let coinbase = CoinbaseBuilder::new(840_000, &ChainParams::MAINNET)
+            .payout(P2WPKH.to_vec(), 312_500_000)
+            .build()
+            .unwrap();
+        let block =
+            Block::new(0x2000_0000, Hash::ZERO, 0x1703_4219, vec![coinbase.clone()]).unwrap();
+        assert!(block.validate_witness_commitment().is_ok());
+
+        let mut block = Block::new(
+            0x2000_0000,
+            Hash::ZERO,
+            0x1703_4219,
+            vec![coinbase, segwit_transaction(0x44)],
+        )
+        .unwrap();
+        assert!(matches!(
+            block.validate_witness_commitment(),
+            Err(Error::UnexpectedWitness)
+        ));
+
+        block.update_witness_commitment().unwrap();
+        assert!(block.validate_witness_commitment().is_ok());
+    }
+
+    #[test]
+    fn set_commitment_replaces_existing() {
+        let mut coinbase = Transaction::try_from(segwit_block().transactions[0].clone()).unwrap();
+        let outputs = coinbase.outputs.len();
+
+        coinbase
+            .set_witness_commitment(&Hash::from_natural_bytes([0x77; 32]))
+            .unwrap();
+
+        assert_eq!(outputs, coinbase.outputs.len());
+        assert_eq!(
+            Block::compute_witness_commitment(&Hash::from_natural_bytes([0x77; 32]), &[0u8; 32]),
+            coinbase.witness_commitment().unwrap()
+        );
+    }
+
+    #[test]
+    fn set_commitment_requires_coinbase() {
+        let mut transaction = Transaction::try_from(segwit_transaction(0x44)).unwrap();
+        assert!(matches!(
+            transaction.set_witness_commitment(&Hash::ZERO),
+            Err(Error::NotCoinbase)
+        ));
+
+        transaction.inputs.clear();
+        assert!(matches!(
+            transaction.set_witness_commitment(&Hash::ZERO),
+            Err(Error::NotCoinbase)
+        ));
+
+        let mut coinbase = Transaction::try_from(segwit_block().transactions[0].clone()).unwrap();
+        coinbase.inputs.push(coinbase.inputs[0].clone());
+        assert!(matches!(
+            coinbase.set_witness_commitment(&Hash::ZERO),
+            Err(Error::NotCoinbase)
+        ));
+    }
+
+    #[test]
+    fn mainnet_empty_block_commitment() {
+        // The commitment output of every mainnet block with only the coinbase,
+        // e.g. the coinbases of empty blocks mined right after the previous block.
+        let output = hex::decode(
+            "6a24aa21a9ede2f61c3f71d1defd3fa999dfa36953755c690689799962b48bebd836974e8cf9",
+        )
+        .unwrap();
+
+        let mut coinbase = Transaction::try_from(
+            CoinbaseBuilder::new(840_000, &ChainParams::MAINNET)
+                .payout(P2WPKH.to_vec(), 312_500_000)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        let witness_merkle_root = Block::compute_witness_merkle_root(&[coinbase.clone().into()]);
+        assert_eq!(Hash::ZERO, witness_merkle_root);
+
+        coinbase
+            .set_witness_commitment(&witness_merkle_root)
+            .unwrap();
+        assert_eq!(output, coinbase.outputs.last().unwrap().script_pubkey);
+        assert_eq!(vec![vec![0u8; 32]], coinbase.inputs[0].witness);
+
+        let block =
+            Block::new(0x2000_0000, Hash::ZERO, 0x1703_4219, vec![coinbase.into()]).unwrap();
+        assert!(block.validate_witness_commitment().is_ok());
+    }
+}
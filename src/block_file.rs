@@ -0,0 +1,488 @@
+pub mod error;
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    block::{Block, BlockHeader, BlockHeaderBytes},
+    chain_params::ChainParams,
+    hash::Hash,
+    header_store::HeaderStore,
+    networking::traits::NetworkInformation,
+};
+
+use error::Error;
+
+/// The largest serialized block: 4 MB of weight with no witness discount.
+pub const MAX_BLOCK_SERIALIZED_SIZE: u32 = 4_000_000;
+/// The file holding the obfuscation key of the block files.
+pub const XOR_KEY_FILE_NAME: &str = "xor.dat";
+
+/// # BlockFileReader
+///
+/// Reads the blocks of a Bitcoin Core block file (`blocks/blk00000.dat`).
+///
+/// Every block is stored as a record:
+/// ```plaintext
+/// f9beb4d9 ........................... Magic bytes of the network
+/// 1d010000 ........................... Size of the block: 285
+/// 0100000000000000...00000000 ........ The serialized block
+/// ```
+///
+/// Blocks are stored in the order they were downloaded, which is not the chain order.
+/// Block files are preallocated, so a file may end with zero bytes.
+///
+/// Since Bitcoin Core 28.0 the files are XOR-obfuscated with the 8-byte key in [`XOR_KEY_FILE_NAME`],
+/// see [`BlockFileReader::with_xor_key`].
+#[derive(Debug)]
+pub struct BlockFileReader<R: Read> {
+    reader: XorReader<R>,
+    magic_bytes: [u8; 4],
+    /// The byte position of the current record.
+    record_start: u64,
+}
+
+/// XORs all read bytes with the key, starting at the key position of `position`.
+#[derive(Debug)]
+struct XorReader<R: Read> {
+    inner: R,
+    key: [u8; 8],
+    position: u64,
+}
+
+impl BlockFileReader<BufReader<File>> {
+    /// Open a block file of the network of `params`.
+    pub fn open(path: impl AsRef<Path>, params: &ChainParams) -> Result<Self, Error> {
+        Ok(Self::new(
+            BufReader::new(File::open(path)?),
+            params.magic_bytes(),
+        ))
+    }
+}
+
+impl<R: Read> BlockFileReader<R> {
+    pub fn new(reader: R, magic_bytes: [u8; 4]) -> Self {
+        Self {
+            reader: XorReader {
+                inner: reader,
+                key: [0u8; 8],
+                position: 0,
+            },
+            magic_bytes,
+            record_start: 0,
+        }
+    }
+
+    /// Deobfuscate the file with `key`. A zero key does nothing.
+    pub fn with_xor_key(mut self, key: [u8; 8]) -> Self {
+        self.reader.key = key;
+        self
+    }
+
+    /// The serialized next block, or [`None`] at the end of the file.
+    ///
+    /// A record cut short by the end of the file is treated as the end, like Bitcoin Core does.
+    pub fn next_block_bytes(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let Some(size) = self.next_record()? else {
+            return Ok(None);
+        };
+
+        let mut bytes = vec![0u8; size as usize];
+        if !self.read_or_end(&mut bytes)? {
+            return Ok(None);
+        }
+
+        Ok(Some(bytes))
+    }
+
+    /// The next block, or [`None`] at the end of the file.
+    pub fn next_block(&mut self) -> Result<Option<Block>, Error> {
+        match self.next_block_bytes()? {
+            Some(bytes) => Ok(Some(Block::from_bytes(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The header of the next block, or [`None`] at the end of the file.
+    ///
+    /// The transactions are skipped without parsing them.
+    pub fn next_header(&mut self) -> Result<Option<BlockHeader>, Error> {
+        let Some(size) = self.next_record()? else {
+            return Ok(None);
+        };
+
+        let mut header = [0u8; 80];
+        if !self.read_or_end(&mut header)? {
+            return Ok(None);
+        }
+
+        let skipped = io::copy(
+            &mut (&mut self.reader).take(size as u64 - 80),
+            &mut io::sink(),
+        )?;
+        if skipped != size as u64 - 80 {
+            self.warn_torn_record();
+            return Ok(None);
+        }
+
+        Ok(Some(BlockHeader::from_bytes(&BlockHeaderBytes::new(
+            header,
+        ))))
+    }
+
+    pub fn blocks(self) -> impl Iterator<Item = Result<Block, Error>> {
+        self.into_iter(Self::next_block)
+    }
+
+    pub fn headers(self) -> impl Iterator<Item = Result<BlockHeader, Error>> {
+        self.into_iter(Self::next_header)
+    }
+
+    fn into_iter<T>(
+        mut self,
+        mut next: impl FnMut(&mut Self) -> Result<Option<T>, Error>,
+    ) -> impl Iterator<Item = Result<T, Error>> {
+        let mut failed = false;
+
+        std::iter::from_fn(move || {
+            if failed {
+                return None;
+            }
+
+            let item = next(&mut self).transpose();
+            failed = matches!(item, Some(Err(_)));
+            item
+        })
+    }
+
+    /// Read the magic bytes and the size of the next record.
+    fn next_record(&mut self) -> Result<Option<u32>, Error> {
+        let offset = self.reader.position;
+        self.record_start = offset;
+        let mut prefix = [0u8; 8];
+        if !self.read_or_end(&mut prefix)? {
+            return Ok(None);
+        }
+
+        let magic = [prefix[0], prefix[1], prefix[2], prefix[3]];
+        if magic == [0u8; 4] {
+            // The preallocated rest of the file.
+            return Ok(None);
+        }
+        if magic != self.magic_bytes {
+            return Err(Error::InvalidMagic { offset, magic });
+        }
+
+        let size = u32::from_le_bytes([prefix[4], prefix[5], prefix[6], prefix[7]]);
+        if size > MAX_BLOCK_SERIALIZED_SIZE {
+            return Err(Error::OversizedBlock { offset, size });
+        }
+        if size < 80 {
+            return Err(Error::UndersizedBlock { offset, size });
+        }
+
+        Ok(Some(size))
+    }
+
+    /// Fill `buf`, returns `false` if the file ends before.
+    fn read_or_end(&mut self, buf: &mut [u8]) -> Result<bool, Error> {
+        match self.reader.read_exact(buf) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                // Ending right before a record is the normal end of a file.
+                if self.reader.position > self.record_start {
+                    self.warn_torn_record();
+                }
+                Ok(false)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn warn_torn_record(&self) {
+        log::warn!(
+            "Block file ends in the middle of the record at byte {}",
+            self.record_start
+        );
+    }
+}
+
+impl<R: Read> Read for XorReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        for byte in &mut buf[..read] {
+            *byte ^= self.key[(self.position % 8) as usize];
+            self.position += 1;
+        }
+
+        Ok(read)
+    }
+}
+
+/// Read the obfuscation key from `xor.dat` in `blocks_dir`.
+///
+/// Returns a zero key if there is no such file, as in block directories of Bitcoin Core before 28.0.
+pub fn read_xor_key(blocks_dir: impl AsRef<Path>) -> Result<[u8; 8], Error> {
+    match fs::read(blocks_dir.as_ref().join(XOR_KEY_FILE_NAME)) {
+        Ok(bytes) => bytes
+            .as_slice()
+            .try_into()
+            .map_err(|_| Error::InvalidXorKey(bytes.len())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok([0u8; 8]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// All `blk*.dat` files in `blocks_dir`, in order.
+pub fn block_file_paths(blocks_dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, Error> {
+    let mut paths = fs::read_dir(blocks_dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.retain(|path| {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("blk") && name.ends_with(".dat"))
+    });
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// Import the headers of all block files in `blocks_dir` into `store`.
+///
+/// Headers are validated by the store. As block files are not in chain order,
+/// headers whose previous header is not known yet are held back until it is.
+/// The headers of each file are inserted at once, so the store syncs once per file.
+/// Returns the count of imported headers.
+pub fn import_headers(
+    blocks_dir: impl AsRef<Path>,
+    store: &mut HeaderStore,
+    params: &ChainParams,
+    now: u32,
+) -> Result<usize, Error> {
+    let blocks_dir = blocks_dir.as_ref();
+    let xor_key = read_xor_key(blocks_dir)?;
+    let height = store.tree().len();
+    // The headers waiting for their previous header, by the hash of the previous header.
+    let mut orphans: HashMap<Hash, Vec<BlockHeader>> = HashMap::new();
+
+    for path in block_file_paths(blocks_dir)? {
+        log::info!("Importing headers from {}", path.display());
+
+        // The connectable headers of this file in chain order, and their hashes.
+        let mut batch = Vec::new();
+        let mut batch_hashes = HashSet::new();
+        let is_known = |store: &HeaderStore, batch_hashes: &HashSet<Hash>, hash: &Hash| {
+            store.tree().contains(hash) || batch_hashes.contains(hash)
+        };
+
+        let reader = BlockFileReader::open(&path, params)?.with_xor_key(xor_key);
+        for header in reader.headers() {
+            let header = header?;
+            // Like the genesis block, which has no previous header.
+            if is_known(store, &batch_hashes, &header.hash()) {
+                continue;
+            }
+            if !is_known(store, &batch_hashes, &header.prev_block_header_hash) {
+                orphans
+                    .entry(header.prev_block_header_hash)
+                    .or_default()
+                    .push(header);
+                continue;
+            }
+
+            let mut connectable = vec![header];
+            while let Some(header) = connectable.pop() {
+                let hash = header.hash();
+                connectable.extend(orphans.remove(&hash).unwrap_or_default());
+                batch_hashes.insert(hash);
+                batch.push(header);
+            }
+        }
+
+        store.insert_headers(batch, params, now)?;
+    }
+
+    if !orphans.is_empty() {
+        log::warn!(
+            "{} headers without a known previous header were not imported",
+            orphans.values().map(Vec::len).sum::<usize>()
+        );
+    }
+
+    Ok(store.tree().len() - height)
+}
+
+#[cfg(test)]
+mod block_file_test {
+    use std::{fs, io::Cursor};
+
+    use crate::{
+        block::{Block, BlockHeader, CoinbaseBuilder},
+        chain_params::ChainParams,
+        header_store::HeaderStore,
+        networking::traits::NetworkInformation,
+        test_util::{REGTEST_TARGET, TempDir},
+    };
+
+    use super::{BlockFileReader, XOR_KEY_FILE_NAME, error::Error, import_headers};
+
+    const XOR_KEY: [u8; 8] = [0x13, 0x37, 0xC0, 0xFF, 0xEE, 0x00, 0x42, 0x99];
+
+    /// Mine regtest blocks on top of `prev`.
+    fn mine_blocks(prev: &BlockHeader, first_height: u32, count: u32) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        for height in first_height..first_height + count {
+            let prev = blocks.last().map(Block::header).unwrap_or(prev);
+            let coinbase = CoinbaseBuilder::new(height, &ChainParams::REGTEST)
+                .payout(vec![0x51], 50 * 100_000_000)
+                .build()
+                .unwrap();
+
+            let mut block =
+                Block::new(0x2000_0000, prev.hash(), REGTEST_TARGET, vec![coinbase]).unwrap();
+            block.header_mut().time = prev.time + 1;
+            while !block.header().check_proof_of_work().unwrap() {
+                block.header_mut().nonce += 1;
+            }
+            blocks.push(block);
+        }
+
+        blocks
+    }
+
+    fn block_file(blocks: &[&Block], magic_bytes: [u8; 4], xor_key: [u8; 8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for block in blocks {
+            let block = block.to_bytes();
+            bytes.extend_from_slice(&magic_bytes);
+            bytes.extend_from_slice(&(block.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&block);
+        }
+        // Preallocated space.
+        bytes.extend_from_slice(&[0u8; 64]);
+
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte ^= xor_key[i % 8];
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn read_blocks() {
+        let params = &ChainParams::MAINNET;
+        let genesis = params.genesis_block();
+        let bytes = block_file(&[&genesis, &genesis], params.magic_bytes(), XOR_KEY);
+
+        let reader =
+            BlockFileReader::new(Cursor::new(&bytes), params.magic_bytes()).with_xor_key(XOR_KEY);
+        let blocks = reader.blocks().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(2, blocks.len());
+        assert_eq!(genesis.to_bytes(), blocks[1].to_bytes());
+
+        let reader =
+            BlockFileReader::new(Cursor::new(&bytes), params.magic_bytes()).with_xor_key(XOR_KEY);
+        let headers = reader.headers().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(vec![genesis.header().clone(); 2], headers);
+
+        // A record cut short ends the file.
+        let bytes = block_file(&[&genesis, &genesis], params.magic_bytes(), [0u8; 8]);
+        let mut reader = BlockFileReader::new(Cursor::new(&bytes[..400]), params.magic_bytes());
+        assert!(reader.next_header().unwrap().is_some());
+        assert!(reader.next_header().unwrap().is_none());
+    }
+
+    #[test]
+    fn read_errors() {
+        let params = &ChainParams::MAINNET;
+        let genesis = params.genesis_block();
+
+        // Without the key the magic bytes do not match.
+        let bytes = block_file(&[&genesis], params.magic_bytes(), XOR_KEY);
+        let mut reader = BlockFileReader::new(Cursor::new(&bytes), params.magic_bytes());
+        assert!(matches!(
+            reader.next_block(),
+            Err(Error::InvalidMagic { offset: 0, .. })
+        ));
+
+        let bytes = block_file(&[&genesis], ChainParams::TESTNET3.magic_bytes(), [0u8; 8]);
+        let mut reader = BlockFileReader::new(Cursor::new(&bytes), params.magic_bytes());
+        assert!(matches!(
+            reader.next_block(),
+            Err(Error::InvalidMagic { offset: 0, .. })
+        ));
+
+        let mut bytes = params.magic_bytes().to_vec();
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        let mut reader = BlockFileReader::new(Cursor::new(&bytes), params.magic_bytes());
+        assert!(matches!(
+            reader.next_block(),
+            Err(Error::OversizedBlock { size: u32::MAX, .. })
+        ));
+
+        // Too small for a header, not the end of the file.
+        let mut bytes = block_file(&[&genesis], params.magic_bytes(), [0u8; 8]);
+        bytes[4..8].copy_from_slice(&79u32.to_le_bytes());
+        let mut reader = BlockFileReader::new(Cursor::new(&bytes), params.magic_bytes());
+        assert!(matches!(
+            reader.next_header(),
+            Err(Error::UndersizedBlock {
+                offset: 0,
+                size: 79
+            })
+        ));
+    }
+
+    #[test]
+    fn import() {
+        let dir = TempDir::new("block_file_import");
+        let blocks_dir = dir.path().join("blocks");
+        fs::create_dir_all(&blocks_dir).unwrap();
+
+        let params = &ChainParams::REGTEST;
+        let genesis = params.genesis_block();
+        let blocks = mine_blocks(genesis.header(), 1, 6);
+
+        // Out of order, split over two files and with a duplicate, as after a parallel download.
+        fs::write(blocks_dir.join(XOR_KEY_FILE_NAME), XOR_KEY).unwrap();
+        fs::write(
+            blocks_dir.join("blk00000.dat"),
+            block_file(
+                &[&genesis, &blocks[0], &blocks[4], &blocks[2], &blocks[1]],
+                params.magic_bytes(),
+                XOR_KEY,
+            ),
+        )
+        .unwrap();
+        fs::write(
+            blocks_dir.join("blk00001.dat"),
+            block_file(
+                &[&blocks[5], &blocks[3], &blocks[4]],
+                params.magic_bytes(),
+                XOR_KEY,
+            ),
+        )
+        .unwrap();
+        fs::write(blocks_dir.join("rev00000.dat"), [0u8; 16]).unwrap();
+
+        let mut store = HeaderStore::open(dir.path().join("headers"), params).unwrap();
+        let now = blocks[5].header().time;
+        assert_eq!(
+            6,
+            import_headers(&blocks_dir, &mut store, params, now).unwrap()
+        );
+        assert_eq!(6, store.height());
+        assert_eq!(blocks[5].block_header_hash256(), store.best_tip().hash);
+
+        // Importing again changes nothing.
+        assert_eq!(
+            0,
+            import_headers(&blocks_dir, &mut store, params, now).unwrap()
+        );
+    }
+}
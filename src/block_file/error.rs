@@ -0,0 +1,20 @@
+use crate::{block, header_store};
+
+/// The `offset` is the byte position of the record in its file.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("IO Error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("Block Error: {0}")]
+    Block(#[from] block::error::Error),
+    #[error("Header Store Error: {0}")]
+    HeaderStore(#[from] header_store::error::Error),
+    #[error("unexpected magic bytes {} at byte {offset}", hex::encode(.magic))]
+    InvalidMagic { offset: u64, magic: [u8; 4] },
+    #[error("the block at byte {offset} is {size} bytes, more than a block can be")]
+    OversizedBlock { offset: u64, size: u32 },
+    #[error("the block at byte {offset} is {size} bytes, less than a block header")]
+    UndersizedBlock { offset: u64, size: u32 },
+    #[error("the xor key has to be 8 bytes but is {0} bytes")]
+    InvalidXorKey(usize),
+}
@@ -0,0 +1,393 @@
+mod difficulty;
+
+use crate::{
+    block::{Block, BlockHeader, Input, OutPoint, Output, Transaction},
+    hash::Hash,
+    networking::{
+        MAGIC_NUMBER_MAINNET, MAGIC_NUMBER_REGTEST, MAGIC_NUMBER_SIGNET, MAGIC_NUMBER_TESTNET3,
+        MAGIC_NUMBER_TESTNET4, NetworkType, traits::NetworkInformation,
+    },
+    u256::U256,
+};
+
+/// The public key of the genesis coinbase output of mainnet, testnet3, signet and regtest.
+const SATOSHI_PUBLIC_KEY: [u8; 65] = [
+    0x04, 0x67, 0x8A, 0xFD, 0xB0, 0xFE, 0x55, 0x48, 0x27, 0x19, 0x67, 0xF1, 0xA6, 0x71, 0x30, 0xB7,
+    0x10, 0x5C, 0xD6, 0xA8, 0x28, 0xE0, 0x39, 0x09, 0xA6, 0x79, 0x62, 0xE0, 0xEA, 0x1F, 0x61, 0xDE,
+    0xB6, 0x49, 0xF6, 0xBC, 0x3F, 0x4C, 0xEF, 0x38, 0xC4, 0xF3, 0x55, 0x04, 0xE5, 0x1E, 0xC1, 0x12,
+    0xDE, 0x5C, 0x38, 0x4D, 0xF7, 0xBA, 0x0B, 0x8D, 0x57, 0x8A, 0x4C, 0x70, 0x2B, 0x6B, 0xF1, 0x1D,
+    0x5F,
+];
+const SATOSHI_TIMESTAMP: &str =
+    "The Times 03/Jan/2009 Chancellor on brink of second bailout for banks";
+
+/// `0x00000000ffff...ff`, the pow limit of mainnet, testnet3 and testnet4.
+const POW_LIMIT_MAINNET: U256 =
+    U256::from_limbs([u64::MAX, u64::MAX, u64::MAX, 0x0000_0000_FFFF_FFFF]);
+/// `0x00000377ae00...00`
+const POW_LIMIT_SIGNET: U256 = U256::from_limbs([0, 0, 0, 0x0000_0377_AE00_0000]);
+/// `0x7fffff...ff`
+const POW_LIMIT_REGTEST: U256 =
+    U256::from_limbs([u64::MAX, u64::MAX, u64::MAX, 0x7FFF_FFFF_FFFF_FFFF]);
+
+/// Two weeks in seconds.
+const TWO_WEEKS: u32 = 14 * 24 * 60 * 60;
+/// Ten minutes in seconds.
+const TEN_MINUTES: u32 = 10 * 60;
+
+/// # ChainParams
+///
+/// The consensus and network parameters of a network, the same as Bitcoin Core´s `CChainParams`.
+///
+/// Use [`ChainParams::from_network`] or [`NetworkType::chain_params`] to look them up.
+///
+/// See [chainparams.cpp](https://github.com/bitcoin/bitcoin/blob/master/src/kernel/chainparams.cpp).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainParams {
+    pub network: NetworkType,
+    /// The message start as a big-endian `u32`, see [`NetworkInformation::magic_number`].
+    pub magic_number: u32,
+    pub default_port: u16,
+    pub genesis: Genesis,
+    /// The easiest allowed target.
+    pub pow_limit: U256,
+    /// The expected seconds between blocks.
+    pub pow_target_spacing: u32,
+    /// The expected seconds of one retarget period.
+    pub pow_target_timespan: u32,
+    /// Allow blocks with the pow limit target if no block was found for twice the target spacing.
+    pub allow_min_difficulty_blocks: bool,
+    /// Never change the target.
+    pub no_retargeting: bool,
+    /// Use the BIP94 timewarp fix: retarget from the first block of the period instead of the last.
+    pub enforce_bip94: bool,
+    /// The height from which the coinbase has to start with the height.
+    pub bip34_height: u32,
+    /// The height from which `OP_CHECKLOCKTIMEVERIFY` is enforced.
+    pub bip65_height: u32,
+    /// The height from which strict DER signatures are enforced.
+    pub bip66_height: u32,
+    pub subsidy_halving_interval: u32,
+}
+
+/// The values the genesis block of a network is built from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Genesis {
+    pub version: i32,
+    pub time: u32,
+    pub target: u32,
+    pub nonce: u32,
+    /// The value of the only coinbase output in satoshis.
+    pub reward: u64,
+    /// The message in the coinbase scriptSig.
+    pub timestamp: &'static str,
+    /// The public key of the only coinbase output (pay-to-pubkey).
+    pub public_key: &'static [u8],
+}
+
+impl ChainParams {
+    pub const MAINNET: Self = Self {
+        network: NetworkType::Mainnet,
+        magic_number: MAGIC_NUMBER_MAINNET,
+        default_port: 8333,
+        genesis: Genesis {
+            version: 1,
+            time: 1_231_006_505,
+            target: 0x1D00_FFFF,
+            nonce: 2_083_236_893,
+            reward: 50 * 100_000_000,
+            timestamp: SATOSHI_TIMESTAMP,
+            public_key: &SATOSHI_PUBLIC_KEY,
+        },
+        pow_limit: POW_LIMIT_MAINNET,
+        pow_target_spacing: TEN_MINUTES,
+        pow_target_timespan: TWO_WEEKS,
+        allow_min_difficulty_blocks: false,
+        no_retargeting: false,
+        enforce_bip94: false,
+        bip34_height: 227_931,
+        bip65_height: 388_381,
+        bip66_height: 363_725,
+        subsidy_halving_interval: 210_000,
+    };
+
+    pub const TESTNET3: Self = Self {
+        network: NetworkType::Testnet3,
+        magic_number: MAGIC_NUMBER_TESTNET3,
+        default_port: 18333,
+        genesis: Genesis {
+            time: 1_296_688_602,
+            nonce: 414_098_458,
+            ..Self::MAINNET.genesis
+        },
+        allow_min_difficulty_blocks: true,
+        bip34_height: 21_111,
+        bip65_height: 581_885,
+        bip66_height: 330_776,
+        ..Self::MAINNET
+    };
+
+    pub const TESTNET4: Self = Self {
+        network: NetworkType::Testnet4,
+        magic_number: MAGIC_NUMBER_TESTNET4,
+        default_port: 48333,
+        genesis: Genesis {
+            time: 1_714_777_860,
+            nonce: 393_743_547,
+            timestamp: "03/May/2024 000000000000000000001ebd58c244970b3aa9d783bb001011fbe8ea8e98e00e",
+            public_key: &[0u8; 33],
+            ..Self::MAINNET.genesis
+        },
+        allow_min_difficulty_blocks: true,
+        enforce_bip94: true,
+        bip34_height: 1,
+        bip65_height: 1,
+        bip66_height: 1,
+        ..Self::MAINNET
+    };
+
+    /// The default signet.
+    pub const SIGNET: Self = Self {
+        network: NetworkType::Signet,
+        magic_number: MAGIC_NUMBER_SIGNET,
+        default_port: 38333,
+        genesis: Genesis {
+            time: 1_598_918_400,
+            target: 0x1E03_77AE,
+            nonce: 52_613_770,
+            ..Self::MAINNET.genesis
+        },
+        pow_limit: POW_LIMIT_SIGNET,
+        bip34_height: 1,
+        bip65_height: 1,
+        bip66_height: 1,
+        ..Self::MAINNET
+    };
+
+    pub const REGTEST: Self = Self {
+        network: NetworkType::Regtest,
+        magic_number: MAGIC_NUMBER_REGTEST,
+        default_port: 18444,
+        genesis: Genesis {
+            time: 1_296_688_602,
+            target: 0x207F_FFFF,
+            nonce: 2,
+            ..Self::MAINNET.genesis
+        },
+        pow_limit: POW_LIMIT_REGTEST,
+        allow_min_difficulty_blocks: true,
+        no_retargeting: true,
+        bip34_height: 1,
+        bip65_height: 1,
+        bip66_height: 1,
+        subsidy_halving_interval: 150,
+        ..Self::MAINNET
+    };
+
+    pub const fn from_network(network: NetworkType) -> &'static Self {
+        match network {
+            NetworkType::Mainnet => &Self::MAINNET,
+            NetworkType::Testnet3 => &Self::TESTNET3,
+            NetworkType::Testnet4 => &Self::TESTNET4,
+            NetworkType::Signet => &Self::SIGNET,
+            NetworkType::Regtest => &Self::REGTEST,
+        }
+    }
+
+    /// The count of blocks between retargets: 2016 on all networks.
+    pub const fn difficulty_adjustment_interval(&self) -> u32 {
+        self.pow_target_timespan / self.pow_target_spacing
+    }
+
+    /// Build the genesis block from [`Self::genesis`].
+    pub fn genesis_block(&self) -> Block {
+        let coinbase = self.genesis.coinbase();
+        let merkle_root_hash = coinbase.txid();
+
+        let header = BlockHeader {
+            version: self.genesis.version,
+            prev_block_header_hash: Hash::ZERO,
+            merkle_root_hash,
+            time: self.genesis.time,
+            target: self.genesis.target,
+            nonce: self.genesis.nonce,
+        };
+
+        Block::from_parts(header, vec![coinbase.into()])
+    }
+
+    pub fn genesis_hash(&self) -> Hash {
+        self.genesis_block().block_header_hash256()
+    }
+}
+
+impl Genesis {
+    /// The genesis coinbase, the same as Bitcoin Core´s `CreateGenesisBlock`.
+    ///
+    /// ```plaintext
+    /// 04ffff001d ......................... Push 486604799
+    /// 0104 ............................... Push 4
+    /// 45 5468652054...73 ................. Push the timestamp message
+    /// ```
+    pub fn coinbase(&self) -> Transaction {
+        const OP_PUSHDATA1: u8 = 0x4C;
+        const OP_CHECKSIG: u8 = 0xAC;
+
+        let mut script_sig = vec![0x04, 0xFF, 0xFF, 0x00, 0x1D, 0x01, 0x04];
+        let timestamp = self.timestamp.as_bytes();
+        if timestamp.len() >= OP_PUSHDATA1 as usize {
+            script_sig.push(OP_PUSHDATA1);
+        }
+        script_sig.push(timestamp.len() as u8);
+        script_sig.extend_from_slice(timestamp);
+
+        let script_pubkey = [
+            &[self.public_key.len() as u8],
+            self.public_key,
+            &[OP_CHECKSIG],
+        ]
+        .concat();
+
+        Transaction {
+            version: 1,
+            inputs: vec![Input {
+                previous_output: OutPoint::NULL,
+                script_sig,
+                sequence: u32::MAX,
+                witness: Vec::new(),
+            }],
+            outputs: vec![Output {
+                value: self.reward,
+                script_pubkey,
+            }],
+            lock_time: 0,
+        }
+    }
+}
+
+impl NetworkInformation for ChainParams {
+    fn port(&self) -> u16 {
+        self.default_port
+    }
+
+    fn magic_bytes(&self) -> [u8; 4] {
+        self.magic_number.to_be_bytes()
+    }
+
+    fn magic_number(&self) -> u32 {
+        self.magic_number
+    }
+}
+
+#[cfg(test)]
+mod chain_params_test {
+    use crate::{
+        block::Block,
+        networking::{NetworkType, traits::NetworkInformation},
+        u256::U256,
+    };
+
+    use super::ChainParams;
+
+    #[test]
+    fn genesis_blocks() {
+        let expected = [
+            (
+                &ChainParams::MAINNET,
+                "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+                "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+            ),
+            (
+                &ChainParams::TESTNET3,
+                "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943",
+                "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+            ),
+            (
+                &ChainParams::TESTNET4,
+                "00000000da84f2bafbbc53dee25a72ae507ff4914b867c565be350b0da8bf043",
+                "7aa0a7ae1e223414cb807e40cd57e667b718e42aaf9306db9102fe28912b7b4e",
+            ),
+            (
+                &ChainParams::SIGNET,
+                "00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6",
+                "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+            ),
+            (
+                &ChainParams::REGTEST,
+                "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206",
+                "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+            ),
+        ];
+
+        for (params, hash, merkle_root) in expected {
+            let genesis = params.genesis_block();
+
+            assert_eq!(hash, genesis.block_header_hash256().to_string());
+            assert_eq!(merkle_root, genesis.header().merkle_root_hash.to_string());
+            assert_eq!(
+                Block::compute_merkle_root_hash(genesis.transactions()).unwrap(),
+                genesis.header().merkle_root_hash
+            );
+            assert!(
+                genesis
+                    .header()
+                    .validate_proof_of_work(&params.pow_limit)
+                    .is_ok()
+            );
+        }
+    }
+
+    #[test]
+    fn mainnet_genesis_bytes() {
+        let genesis = ChainParams::MAINNET.genesis_block();
+
+        assert_eq!(285, genesis.to_bytes().len());
+        assert_eq!(
+            genesis.to_bytes(),
+            Block::from_bytes(&genesis.to_bytes()).unwrap().to_bytes()
+        );
+    }
+
+    #[test]
+    fn networks() {
+        let networks = [
+            (NetworkType::Mainnet, 8333, "f9beb4d9"),
+            (NetworkType::Testnet3, 18333, "0b110907"),
+            (NetworkType::Testnet4, 48333, "1c163f28"),
+            (NetworkType::Signet, 38333, "0a03cf40"),
+            (NetworkType::Regtest, 18444, "fabfb5da"),
+        ];
+
+        for (network, port, magic) in networks {
+            let params = ChainParams::from_network(network);
+
+            assert_eq!(network, params.network);
+            assert_eq!(port, network.port());
+            assert_eq!(magic, hex::encode(network.magic_bytes()));
+            assert_eq!(
+                Some(network),
+                NetworkType::try_from_magic_bytes(&network.magic_bytes())
+            );
+            assert_eq!(2016, params.difficulty_adjustment_interval());
+        }
+        assert_eq!(
+            None,
+            NetworkType::try_from_magic_bytes(&[0xDE, 0xAD, 0xBE, 0xEF])
+        );
+
+        let mut pow_limit = [0xFFu8; 32];
+        pow_limit[..4].fill(0);
+        assert_eq!(
+            U256::from_be_bytes(pow_limit),
+            ChainParams::MAINNET.pow_limit
+        );
+        assert_eq!(
+            "00000377ae000000000000000000000000000000000000000000000000000000",
+            hex::encode(ChainParams::SIGNET.pow_limit.to_be_bytes())
+        );
+        assert_eq!(
+            (U256::ONE << 255) - U256::ONE,
+            ChainParams::REGTEST.pow_limit
+        );
+    }
+}
@@ -0,0 +1,344 @@
+use crate::{
+    block::{BlockHeader, error::Error},
+    chain_params::ChainParams,
+    u256::U256,
+};
+
+/// # Difficulty retargeting
+///
+/// Every [`ChainParams::difficulty_adjustment_interval`] blocks the target is scaled by
+/// the actual timespan of the last period divided by [`ChainParams::pow_target_timespan`].
+/// The actual timespan is clamped to a quarter and four times the target timespan.
+///
+/// The same as Bitcoin Core, the timespan is measured from the first to the last block of the period,
+/// which only covers 2015 block intervals instead of 2016.
+///
+/// Between retargets the target stays the same, except on networks with
+/// [`ChainParams::allow_min_difficulty_blocks`] (testnet):
+/// a block more than twice the target spacing after its predecessor may use the pow limit,
+/// and otherwise uses the target of the last block which was not such a min-difficulty block.
+///
+/// See [pow.cpp](https://github.com/bitcoin/bitcoin/blob/master/src/pow.cpp).
+impl ChainParams {
+    /// The compact target (nBits) the block after `prev_headers` must have,
+    /// the same as Bitcoin Core´s `GetNextWorkRequired`.
+    ///
+    /// - `prev_headers`: the headers up to and including the tip in chain order, oldest first.
+    ///   At least the last [`Self::difficulty_adjustment_interval`] headers (or all down to genesis) are required.
+    /// - `tip_height`: the height of the last header of `prev_headers`,
+    ///   at least `prev_headers.len() - 1`.
+    /// - `new_header_time`: the time of the new block.
+    ///
+    /// Returns the compact pow limit if `prev_headers` is empty, the block after genesis.
+    pub fn next_work_required(
+        &self,
+        prev_headers: &[BlockHeader],
+        tip_height: u32,
+        new_header_time: u32,
+    ) -> Result<u32, Error> {
+        // Genesis is at height 0, there are at most `tip_height + 1` headers up to the tip.
+        if prev_headers.len() > tip_height as usize + 1 {
+            return Err(Error::TooManyPrevHeaders {
+                tip_height,
+                actual: prev_headers.len(),
+            });
+        }
+
+        self.next_work_required_from_ancestors(
+            prev_headers.iter().rev(),
+            tip_height,
+            new_header_time,
+        )
+    }
+
+    /// [`Self::next_work_required`] with the headers walked back from the tip, tip first.
+    ///
+    /// Only the headers needed are taken from `ancestors`:
+    /// the tip, the first block of the period on a retarget
+    /// or the walk back to the last block which is not a min-difficulty block.
+    pub fn next_work_required_from_ancestors<'a>(
+        &self,
+        mut ancestors: impl Iterator<Item = &'a BlockHeader>,
+        tip_height: u32,
+        new_header_time: u32,
+    ) -> Result<u32, Error> {
+        let pow_limit = self.pow_limit.to_compact(false);
+        let Some(last) = ancestors.next() else {
+            return Ok(pow_limit);
+        };
+        let interval = self.difficulty_adjustment_interval();
+        let missing = |actual: usize| Error::MissingPrevHeaders {
+            needed: interval as usize,
+            actual,
+        };
+
+        if !(tip_height + 1).is_multiple_of(interval) {
+            if !self.allow_min_difficulty_blocks {
+                return Ok(last.target);
+            }
+
+            // A block after twice the target spacing may be mined with the pow limit.
+            if new_header_time > last.time.saturating_add(self.pow_target_spacing * 2) {
+                return Ok(pow_limit);
+            }
+
+            // Otherwise return the target of the last block which is not a min-difficulty block.
+            // The walk ends at the latest at genesis, the first block of a period.
+            let mut header = last;
+            let mut height = tip_height;
+            let mut count = 1;
+            while !height.is_multiple_of(interval) && header.target == pow_limit {
+                header = ancestors.next().ok_or_else(|| missing(count))?;
+                height -= 1;
+                count += 1;
+            }
+
+            return Ok(header.target);
+        }
+
+        if self.no_retargeting {
+            return Ok(last.target);
+        }
+
+        // Go back to the first block of the period: `interval - 1` blocks, not `interval`.
+        let mut first = last;
+        for count in 1..interval as usize {
+            first = ancestors.next().ok_or_else(|| missing(count))?;
+        }
+
+        Ok(self.calculate_next_work_required(first, last))
+    }
+
+    /// The retargeted compact target for the period from `first` to `last`,
+    /// the same as Bitcoin Core´s `CalculateNextWorkRequired`.
+    ///
+    /// With [`Self::enforce_bip94`] the target of `first` is scaled instead of the one of `last`,
+    /// so a min-difficulty block at the end of a period does not reset the difficulty.
+    pub fn calculate_next_work_required(&self, first: &BlockHeader, last: &BlockHeader) -> u32 {
+        if self.no_retargeting {
+            return last.target;
+        }
+
+        let actual_timespan = (last.time as i64 - first.time as i64).clamp(
+            self.pow_target_timespan as i64 / 4,
+            self.pow_target_timespan as i64 * 4,
+        );
+
+        let target = if self.enforce_bip94 {
+            first.target
+        } else {
+            last.target
+        };
+        let mut new_target = U256::from_compact(target).target;
+        new_target = new_target * U256::from_u64(actual_timespan as u64);
+        new_target = new_target / U256::from_u64(self.pow_target_timespan as u64);
+
+        if new_target > self.pow_limit {
+            new_target = self.pow_limit;
+        }
+
+        new_target.to_compact(false)
+    }
+}
+
+#[cfg(test)]
+mod difficulty_test {
+    use crate::{
+        block::{BlockHeader, error::Error},
+        chain_params::ChainParams,
+        hash::Hash,
+    };
+
+    fn header(time: u32, target: u32) -> BlockHeader {
+        BlockHeader {
+            version: 1,
+            prev_block_header_hash: Hash::ZERO,
+            merkle_root_hash: Hash::ZERO,
+            time,
+            target,
+            nonce: 0,
+        }
+    }
+
+    /// The test vectors of Bitcoin Core´s `pow_tests.cpp`.
+    #[test]
+    fn calculate_next_work_required() {
+        let params = &ChainParams::MAINNET;
+
+        // Block 32255, the first retarget with a change.
+        assert_eq!(
+            0x1D00_D86A,
+            params.calculate_next_work_required(
+                &header(1_261_130_161, 0x1D00_FFFF),
+                &header(1_262_152_739, 0x1D00_FFFF)
+            )
+        );
+        // Block 2015, capped at the pow limit.
+        assert_eq!(
+            0x1D00_FFFF,
+            params.calculate_next_work_required(
+                &header(1_231_006_505, 0x1D00_FFFF),
+                &header(1_233_061_996, 0x1D00_FFFF)
+            )
+        );
+        // Block 68543, clamped to a quarter of the timespan.
+        assert_eq!(
+            0x1C01_68FD,
+            params.calculate_next_work_required(
+                &header(1_279_008_237, 0x1C05_A3F4),
+                &header(1_279_297_671, 0x1C05_A3F4)
+            )
+        );
+        // Block 46367, clamped to four times the timespan.
+        assert_eq!(
+            0x1D00_E1FD,
+            params.calculate_next_work_required(
+                &header(1_263_163_443, 0x1C38_7F6F),
+                &header(1_269_211_443, 0x1C38_7F6F)
+            )
+        );
+    }
+
+    #[test]
+    fn next_work_required() {
+        let params = &ChainParams::MAINNET;
+        assert_eq!(0x1D00_FFFF, params.next_work_required(&[], 0, 0).unwrap());
+
+        // A period of exactly half the target timespan doubles the difficulty.
+        let start = 1_600_000_000;
+        let headers = (0..2016)
+            .map(|i| header(start + i * 300, 0x1C05_A3F4))
+            .collect::<Vec<_>>();
+        let tip_height = 2016 * 100 - 1;
+
+        let expected = params.calculate_next_work_required(&headers[0], &headers[2015]);
+        assert_eq!(
+            expected,
+            params
+                .next_work_required(&headers, tip_height, start + 2016 * 300)
+                .unwrap()
+        );
+        assert_ne!(0x1C05_A3F4, expected);
+
+        // Between retargets the target stays the same, even after a long time on mainnet.
+        assert_eq!(
+            0x1C05_A3F4,
+            params
+                .next_work_required(&headers, tip_height - 1, u32::MAX)
+                .unwrap()
+        );
+
+        assert!(matches!(
+            params.next_work_required(&headers[1..], tip_height, 0),
+            Err(Error::MissingPrevHeaders {
+                needed: 2016,
+                actual: 2015
+            })
+        ));
+
+        assert!(matches!(
+            params.next_work_required(&headers, 2014, 0),
+            Err(Error::TooManyPrevHeaders {
+                tip_height: 2014,
+                actual: 2016
+            })
+        ));
+
+        // Only the needed headers are walked.
+        let mut taken = 0;
+        params
+            .next_work_required_from_ancestors(
+                headers.iter().rev().inspect(|_| taken += 1),
+                tip_height - 1,
+                0,
+            )
+            .unwrap();
+        assert_eq!(1, taken);
+        params
+            .next_work_required_from_ancestors(
+                headers.iter().rev().inspect(|_| taken += 1),
+                tip_height,
+                0,
+            )
+            .unwrap();
+        assert_eq!(1 + 2016, taken);
+
+        let regtest = &ChainParams::REGTEST;
+        let headers = [header(1_000, 0x207F_FFFF), header(1_001, 0x207F_FFFF)];
+        assert_eq!(
+            0x207F_FFFF,
+            regtest.next_work_required(&headers, 2015, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn testnet3_min_difficulty() {
+        let params = &ChainParams::TESTNET3;
+        let target = 0x1C05_A3F4;
+        let min_difficulty = 0x1D00_FFFF;
+
+        // Height 4032 starts a period, followed by normal and min-difficulty blocks.
+        let mut headers = vec![header(10_000, target), header(10_600, target)];
+        headers.push(header(12_000, min_difficulty));
+        headers.push(header(13_300, min_difficulty));
+        let tip_height = 4032 + 3;
+        let tip_time = 13_300;
+
+        // More than 20 minutes after the tip.
+        assert_eq!(
+            min_difficulty,
+            params
+                .next_work_required(&headers, tip_height, tip_time + 1201)
+                .unwrap()
+        );
+        // Otherwise walk back to the last block which is not a min-difficulty block.
+        assert_eq!(
+            target,
+            params
+                .next_work_required(&headers, tip_height, tip_time + 1200)
+                .unwrap()
+        );
+
+        // The first block of a period ends the walk back.
+        let headers = [
+            header(10_000, min_difficulty),
+            header(11_300, min_difficulty),
+        ];
+        assert_eq!(
+            min_difficulty,
+            params.next_work_required(&headers, 4033, 11_400).unwrap()
+        );
+
+        // Mainnet never allows min-difficulty blocks.
+        assert_eq!(
+            min_difficulty,
+            ChainParams::MAINNET
+                .next_work_required(&headers, 4033, u32::MAX)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn testnet4_bip94() {
+        let testnet3 = &ChainParams::TESTNET3;
+        let testnet4 = &ChainParams::TESTNET4;
+        let target = 0x1C05_A3F4;
+
+        // The last block of the period is a min-difficulty block.
+        let first = header(1_000_000, target);
+        let last = header(1_000_000 + 2015 * 600, 0x1D00_FFFF);
+
+        assert_ne!(
+            testnet3.calculate_next_work_required(&first, &last),
+            testnet4.calculate_next_work_required(&first, &last)
+        );
+        assert_eq!(
+            testnet3.calculate_next_work_required(&first, &header(last.time, target)),
+            testnet4.calculate_next_work_required(&first, &last)
+        );
+        assert_ne!(
+            0x1D00_FFFF,
+            testnet4.calculate_next_work_required(&first, &last)
+        );
+    }
+}
@@ -0,0 +1,386 @@
+pub mod error;
+
+use std::io::{self, Read, Write};
+
+use crate::hash::Hash;
+
+use error::Error;
+
+/// The largest compact size Bitcoin Core accepts (`MAX_SIZE`).
+pub const MAX_COMPACT_SIZE: u64 = 0x0200_0000;
+
+/// Types which can be written in consensus encoding.
+///
+/// The serialization used by blocks, transactions and P2P messages:
+/// little-endian integers, fixed size byte arrays,
+/// [`CompactSize`] integers and compact size prefixed byte strings and vectors.
+pub trait Encodable {
+    /// Encode into `writer` and return the count of written bytes.
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error>;
+
+    fn to_consensus_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.consensus_encode(&mut bytes)
+            .expect("Writing into a Vec never fails.");
+
+        bytes
+    }
+}
+
+/// Types which can be read from consensus encoding.
+pub trait Decodable: Sized {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error>;
+
+    /// Decode from `bytes`. All bytes have to be consumed.
+    fn from_consensus_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut cursor = bytes;
+        let value = Self::consensus_decode(&mut cursor)?;
+
+        if cursor.is_empty() {
+            Ok(value)
+        } else {
+            Err(Error::TrailingBytes(cursor.len()))
+        }
+    }
+}
+
+/// A [compact size](https://learnmeabitcoin.com/technical/general/compact-size/) unsigned integer.
+///
+/// ```plaintext
+/// 0x00..=0xFC ........ 1 byte
+/// 0xFD + u16 ......... 3 bytes
+/// 0xFE + u32 ......... 5 bytes
+/// 0xFF + u64 ......... 9 bytes
+/// ```
+///
+/// Decoding rejects non-canonical encodings and sizes above [`MAX_COMPACT_SIZE`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CompactSize(pub u64);
+
+/// Read exactly `buf.len()` bytes.
+///
+/// Unlike [`Read::read_exact`] this reports how many bytes were missing.
+pub fn read_exact<R: Read + ?Sized>(reader: &mut R, buf: &mut [u8]) -> Result<(), Error> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => {
+                return Err(Error::UnexpectedEnd {
+                    needed: buf.len() - filled,
+                });
+            }
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn read_u8<R: Read + ?Sized>(reader: &mut R) -> Result<u8, Error> {
+    let mut byte = [0u8; 1];
+    read_exact(reader, &mut byte)?;
+
+    Ok(byte[0])
+}
+
+pub fn write_u8<W: Write + ?Sized>(writer: &mut W, byte: u8) -> Result<usize, io::Error> {
+    writer.write_all(&[byte])?;
+
+    Ok(1)
+}
+
+/// Encode `items` with a compact size count prefix.
+pub fn encode_list<T: Encodable, W: Write + ?Sized>(
+    items: &[T],
+    writer: &mut W,
+) -> Result<usize, io::Error> {
+    let mut len = CompactSize(items.len() as u64).consensus_encode(writer)?;
+    for item in items {
+        len += item.consensus_encode(writer)?;
+    }
+
+    Ok(len)
+}
+
+/// Decode a compact size count prefixed list.
+pub fn decode_list<T: Decodable, R: Read + ?Sized>(reader: &mut R) -> Result<Vec<T>, Error> {
+    /// Never preallocate more than this many items for an untrusted count.
+    const MAX_PREALLOCATION: usize = 1024;
+
+    let count = CompactSize::consensus_decode(reader)?.0 as usize;
+    let mut items = Vec::with_capacity(count.min(MAX_PREALLOCATION));
+    for _ in 0..count {
+        items.push(T::consensus_decode(reader)?);
+    }
+
+    Ok(items)
+}
+
+impl CompactSize {
+    /// The encoded length in bytes.
+    pub const fn encoded_len(&self) -> usize {
+        match self.0 {
+            0..0xFD => 1,
+            0xFD..=0xFFFF => 3,
+            0x1_0000..=0xFFFF_FFFF => 5,
+            _ => 9,
+        }
+    }
+}
+
+impl Encodable for CompactSize {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        match self.0 {
+            0..0xFD => write_u8(writer, self.0 as u8),
+            0xFD..=0xFFFF => {
+                write_u8(writer, 0xFD)?;
+                Ok(1 + (self.0 as u16).consensus_encode(writer)?)
+            }
+            0x1_0000..=0xFFFF_FFFF => {
+                write_u8(writer, 0xFE)?;
+                Ok(1 + (self.0 as u32).consensus_encode(writer)?)
+            }
+            _ => {
+                write_u8(writer, 0xFF)?;
+                Ok(1 + self.0.consensus_encode(writer)?)
+            }
+        }
+    }
+}
+
+impl Decodable for CompactSize {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let (size, min) = match read_u8(reader)? {
+            0xFD => (u16::consensus_decode(reader)? as u64, 0xFD),
+            0xFE => (u32::consensus_decode(reader)? as u64, 0x1_0000),
+            0xFF => (u64::consensus_decode(reader)?, 0x1_0000_0000),
+            size => (size as u64, 0),
+        };
+
+        if size < min {
+            return Err(Error::NonCanonicalCompactSize(size));
+        }
+        if size > MAX_COMPACT_SIZE {
+            return Err(Error::OversizedCompactSize(size));
+        }
+
+        Ok(Self(size))
+    }
+}
+
+macro_rules! impl_int_encoding {
+    ($($int:ty),*) => {
+        $(
+            impl Encodable for $int {
+                fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+                    writer.write_all(&self.to_le_bytes())?;
+
+                    Ok(size_of::<$int>())
+                }
+            }
+
+            impl Decodable for $int {
+                fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+                    let mut bytes = [0u8; size_of::<$int>()];
+                    read_exact(reader, &mut bytes)?;
+
+                    Ok(<$int>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_int_encoding!(u16, u32, u64, i32, i64);
+
+impl Encodable for bool {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        write_u8(writer, *self as u8)
+    }
+}
+
+impl Decodable for bool {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(read_u8(reader)? != 0)
+    }
+}
+
+impl<const N: usize> Encodable for [u8; N] {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        writer.write_all(self)?;
+
+        Ok(N)
+    }
+}
+
+impl<const N: usize> Decodable for [u8; N] {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let mut bytes = [0u8; N];
+        read_exact(reader, &mut bytes)?;
+
+        Ok(bytes)
+    }
+}
+
+/// A compact size prefixed byte string.
+impl Encodable for Vec<u8> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let len = CompactSize(self.len() as u64).consensus_encode(writer)?;
+        writer.write_all(self)?;
+
+        Ok(len + self.len())
+    }
+}
+
+/// A compact size prefixed byte string.
+impl Decodable for Vec<u8> {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        /// Never preallocate more than this many bytes for an untrusted length.
+        const MAX_PREALLOCATION: usize = 0x1_0000;
+
+        let len = CompactSize::consensus_decode(reader)?.0 as usize;
+        // The buffer only grows with the bytes actually read.
+        let mut bytes = Vec::with_capacity(len.min(MAX_PREALLOCATION));
+        reader.take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() < len {
+            return Err(Error::UnexpectedEnd {
+                needed: len - bytes.len(),
+            });
+        }
+
+        Ok(bytes)
+    }
+}
+
+/// A compact size prefixed vector.
+impl<T: Encodable> Encodable for Vec<T> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        encode_list(self, writer)
+    }
+}
+
+/// A compact size prefixed vector.
+impl<T: Decodable> Decodable for Vec<T> {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        decode_list(reader)
+    }
+}
+
+/// A hash in natural byte order.
+impl Encodable for Hash {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        self.as_bytes().consensus_encode(writer)
+    }
+}
+
+/// A hash in natural byte order.
+impl Decodable for Hash {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Hash::from_natural_bytes(<[u8; 32]>::consensus_decode(
+            reader,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod encoding_test {
+    use super::{CompactSize, Decodable, Encodable, error::Error};
+
+    #[test]
+    fn compact_size() {
+        for (size, hex) in [
+            (0u64, "00"),
+            (0xFC, "fc"),
+            (0xFD, "fdfd00"),
+            (0xFFFF, "fdffff"),
+            (0x1_0000, "fe00000100"),
+            (0x0200_0000, "fe00000002"),
+        ] {
+            let bytes = CompactSize(size).to_consensus_bytes();
+
+            assert_eq!(hex, hex::encode(&bytes));
+            assert_eq!(bytes.len(), CompactSize(size).encoded_len());
+            assert_eq!(
+                CompactSize(size),
+                CompactSize::from_consensus_bytes(&bytes).unwrap()
+            );
+        }
+
+        assert_eq!(
+            "ff0000000001000000",
+            hex::encode(CompactSize(0x1_0000_0000).to_consensus_bytes())
+        );
+    }
+
+    #[test]
+    fn compact_size_errors() {
+        assert!(matches!(
+            CompactSize::from_consensus_bytes(&[0xFD, 0xFC, 0x00]),
+            Err(Error::NonCanonicalCompactSize(0xFC))
+        ));
+        assert!(matches!(
+            CompactSize::from_consensus_bytes(&[0xFE, 0xFF, 0xFF, 0x00, 0x00]),
+            Err(Error::NonCanonicalCompactSize(0xFFFF))
+        ));
+        assert!(matches!(
+            CompactSize::from_consensus_bytes(&[0xFE, 0x01, 0x00, 0x00, 0x02]),
+            Err(Error::OversizedCompactSize(0x0200_0001))
+        ));
+        assert!(matches!(
+            CompactSize::from_consensus_bytes(&[0xFE, 0x01]),
+            Err(Error::UnexpectedEnd { needed: 3 })
+        ));
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!("78563412", hex::encode(0x1234_5678u32.to_consensus_bytes()));
+        assert_eq!("ffffffff", hex::encode((-1i32).to_consensus_bytes()));
+        assert_eq!(
+            0x0102_0304_0506_0708u64,
+            u64::from_consensus_bytes(&[8, 7, 6, 5, 4, 3, 2, 1]).unwrap()
+        );
+        assert!(matches!(
+            u32::from_consensus_bytes(&[1, 2]),
+            Err(Error::UnexpectedEnd { needed: 2 })
+        ));
+        assert!(matches!(
+            u16::from_consensus_bytes(&[1, 2, 3]),
+            Err(Error::TrailingBytes(1))
+        ));
+    }
+
+    #[test]
+    fn vectors() {
+        let bytes = vec![0xAAu8; 3];
+        let encoded = bytes.to_consensus_bytes();
+        assert_eq!("03aaaaaa", hex::encode(&encoded));
+        assert_eq!(bytes, Vec::<u8>::from_consensus_bytes(&encoded).unwrap());
+
+        let stack = vec![vec![0x01u8], Vec::new(), vec![0x02, 0x03]];
+        let encoded = stack.to_consensus_bytes();
+        assert_eq!("03010100020203", hex::encode(&encoded));
+        assert_eq!(
+            stack,
+            Vec::<Vec<u8>>::from_consensus_bytes(&encoded).unwrap()
+        );
+
+        let ints = vec![1u32, 2];
+        assert_eq!("020100000002000000", hex::encode(ints.to_consensus_bytes()));
+
+        assert!(matches!(
+            Vec::<u8>::from_consensus_bytes(&[0x05, 0x00]),
+            Err(Error::UnexpectedEnd { needed: 4 })
+        ));
+
+        // A maximum length announced by a few bytes.
+        assert!(matches!(
+            Vec::<u8>::from_consensus_bytes(&[0xFE, 0x00, 0x00, 0x00, 0x02, 0x00]),
+            Err(Error::UnexpectedEnd {
+                needed: 0x01FF_FFFF
+            })
+        ));
+    }
+}
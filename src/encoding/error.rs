@@ -0,0 +1,17 @@
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("IO Error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("unexpected end of data: {needed} more bytes needed")]
+    UnexpectedEnd { needed: usize },
+    #[error("{0} trailing bytes")]
+    TrailingBytes(usize),
+    #[error("non-canonical compact size {0}")]
+    NonCanonicalCompactSize(u64),
+    #[error("compact size {0} is too large")]
+    OversizedCompactSize(u64),
+    #[error("invalid segwit flag {0:#04x}")]
+    InvalidSegwitFlag(u8),
+    #[error("segwit serialization without any witness")]
+    SuperfluousWitness,
+}
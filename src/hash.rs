@@ -1,98 +1,197 @@
+pub mod error;
+
+use std::{cmp::Ordering, fmt, str::FromStr};
+
 use sha2::{Digest, Sha256};
 
+use crate::block::BlockHeaderBytes;
+
+use error::Error;
+
 pub struct Hash256;
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub enum Hash {
-    /// The natural byte order.
-    ///
-    /// The byte order as it comes out of the hash function.
-    NaturalByte { hash: String },
-    /// The reverse byte order.
-    ///
-    /// The byte order as shown on blockchain explorers.
-    ReverseByte { hash: String },
+/// A Hash256 hasher for block headers with a cached SHA256 midstate.
+///
+/// The first 64 bytes of a header (version, previous block header hash and most of the merkle root)
+/// are compressed once on creation.
+/// Every hash afterwards only compresses the 16-byte tail (end of merkle root, time, target, nonce)
+/// and runs the second SHA256.
+/// Changing the version (see [`VersionRolling`](crate::block::VersionRolling)) recomputes the midstate.
+#[derive(Clone)]
+pub struct HeaderHasher {
+    /// Bytes 0..64 of the header.
+    head: [u8; 64],
+    /// The SHA256 state after the first 64 bytes.
+    midstate: Sha256,
+    /// Bytes 64..80 of the header.
+    tail: [u8; 16],
 }
 
+/// A 32 byte hash.
+///
+/// The bytes are stored in natural byte order, the byte order as it comes out of the hash function.
+/// [`Display`](fmt::Display) and [`FromStr`] use the reverse byte order as shown on blockchain explorers.
+///
+/// Hashes compare as 256-bit little-endian unsigned integers,
+/// which is how a header hash is compared against its target.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Hash([u8; 32]);
+
+/// A [`Hash`] in natural byte order.
+///
+/// The byte order as it comes out of the hash function and as it is serialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NaturalBytes(pub [u8; 32]);
+
+/// A [`Hash`] in reverse byte order.
+///
+/// The byte order as shown on blockchain explorers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReverseBytes(pub [u8; 32]);
+
 impl Hash256 {
     /// The Hash256 algorithm for Bitcoin.
     pub fn digest(data: &[u8]) -> Hash {
         let generic_array_hash1 = Sha256::digest(data);
         let generic_array_hash2 = Sha256::digest(generic_array_hash1);
-        let string_hash = hex::encode(generic_array_hash2);
 
-        Hash::NaturalByte { hash: string_hash }
+        Hash(generic_array_hash2.into())
     }
 }
 
-impl Hash {
-    pub fn from_natural_byte(hash: String) -> Self {
-        Self::NaturalByte { hash }
-    }
+impl HeaderHasher {
+    const MIDSTATE_LEN: usize = 64;
+    const VERSION_OFFSET: usize = 0;
+    const TIME_OFFSET: usize = 4;
+    const NONCE_OFFSET: usize = 12;
+
+    pub fn new(header: &BlockHeaderBytes) -> Self {
+        let bytes = header.as_bytes();
+        let mut head = [0u8; 64];
+        head.copy_from_slice(&bytes[..Self::MIDSTATE_LEN]);
+
+        let mut tail = [0u8; 16];
+        tail.copy_from_slice(&bytes[Self::MIDSTATE_LEN..]);
 
-    pub fn from_natural_byte_str(hash: &str) -> Self {
-        Self::NaturalByte {
-            hash: hash.to_string(),
+        Self {
+            head,
+            midstate: Self::compute_midstate(&head),
+            tail,
         }
     }
 
-    pub fn from_reverse_byte(hash: String) -> Self {
-        Self::NaturalByte { hash }
+    /// Hash the header with the nonce currently set in the tail.
+    pub fn hash(&self) -> Hash {
+        Self::finalize(self.midstate.clone(), &self.tail)
     }
 
-    pub fn from_reverse_byte_str(hash: &str) -> Self {
-        Self::ReverseByte {
-            hash: hash.to_string(),
-        }
+    /// Hash the header with `nonce` without changing the stored tail.
+    pub fn hash_with_nonce(&self, nonce: u32) -> Hash {
+        let mut tail = self.tail;
+        tail[Self::NONCE_OFFSET..].copy_from_slice(&nonce.to_le_bytes());
+
+        Self::finalize(self.midstate.clone(), &tail)
     }
 
-    pub fn to_natural_byte(self) -> Self {
-        if let Self::ReverseByte { hash: old_hash } = self {
-            let hash = Self::reverse_hex(&old_hash);
+    pub fn set_nonce(&mut self, nonce: u32) {
+        self.tail[Self::NONCE_OFFSET..].copy_from_slice(&nonce.to_le_bytes());
+    }
 
-            Self::NaturalByte { hash }
-        } else {
-            self
-        }
+    /// Update the time.
+    ///
+    /// The time is in the tail, so the midstate stays valid.
+    pub fn set_time(&mut self, time: u32) {
+        self.tail[Self::TIME_OFFSET..Self::TIME_OFFSET + 4].copy_from_slice(&time.to_le_bytes());
     }
 
-    pub fn to_reverse_byte(self) -> Self {
-        if let Self::NaturalByte { hash: old_hash } = self {
-            let hash = Self::reverse_hex(&old_hash);
+    /// Update the version and recompute the midstate.
+    ///
+    /// The version is in the first 64 bytes, so this costs one extra SHA256 compression.
+    /// Roll the nonce first and the version after all nonces are exhausted.
+    pub fn set_version(&mut self, version: i32) {
+        self.head[Self::VERSION_OFFSET..Self::VERSION_OFFSET + 4]
+            .copy_from_slice(&version.to_le_bytes());
+        self.midstate = Self::compute_midstate(&self.head);
+    }
 
-            Self::ReverseByte { hash }
-        } else {
-            self
-        }
+    fn compute_midstate(head: &[u8; 64]) -> Sha256 {
+        let mut midstate = Sha256::new();
+        midstate.update(head);
+
+        midstate
+    }
+
+    fn finalize(mut first: Sha256, tail: &[u8; 16]) -> Hash {
+        first.update(tail);
+        let second = Sha256::digest(first.finalize());
+
+        Hash(second.into())
+    }
+}
+
+impl Hash {
+    /// The all-zero hash.
+    pub const ZERO: Self = Self([0u8; 32]);
+
+    pub const fn from_natural_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub const fn from_reverse_bytes(mut bytes: [u8; 32]) -> Self {
+        bytes.reverse();
+        Self(bytes)
+    }
+
+    /// Parse a hex string in natural byte order.
+    pub fn from_natural_hex(hex: &str) -> Result<Self, Error> {
+        let mut bytes = [0u8; 32];
+        hex::decode_to_slice(hex, &mut bytes)?;
+
+        Ok(Self(bytes))
+    }
+
+    /// Parse a hex string in reverse byte order.
+    ///
+    /// The same as [`FromStr`].
+    pub fn from_reverse_hex(hex: &str) -> Result<Self, Error> {
+        let mut bytes = [0u8; 32];
+        hex::decode_to_slice(hex, &mut bytes)?;
+
+        Ok(Self::from_reverse_bytes(bytes))
     }
 
-    pub fn to_string(&self) -> String {
-        self.inner().to_string()
+    pub const fn natural_bytes(&self) -> NaturalBytes {
+        NaturalBytes(self.0)
     }
 
-    pub fn as_str(&self) -> &str {
-        self.inner()
+    pub const fn reverse_bytes(&self) -> ReverseBytes {
+        let mut bytes = self.0;
+        bytes.reverse();
+        ReverseBytes(bytes)
     }
 
-    pub fn to_bytes(&self) -> [u8; 32] {
-        Self::decode_to_bytes(self.inner())
+    /// The bytes in natural byte order.
+    pub const fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// The bytes in natural byte order.
+    pub const fn to_bytes(self) -> [u8; 32] {
+        self.0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0u8; 32]
     }
 
     /// The checksum of this [`Hash`].
     ///
-    /// The checksum are the first 4 chars from a hash.
+    /// The checksum are the first 4 bytes from a hash in natural byte order.
     ///
     /// See [Checksum](https://learnmeabitcoin.com/technical/keys/checksum/).
     pub fn checksum(&self) -> [u8; 4] {
-        let bytes = match self {
-            Self::NaturalByte { hash } => Self::decode_to_bytes(hash),
-            Self::ReverseByte { hash } => {
-                let reversed = Self::reverse_hex(hash);
-                Self::decode_to_bytes(&reversed)
-            }
-        };
         let mut checksum = [0u8; 4];
-        checksum.copy_from_slice(&bytes[..4]);
+        checksum.copy_from_slice(&self.0[..4]);
 
         checksum
     }
@@ -101,41 +200,87 @@ impl Hash {
     pub fn check(&self, checksum: &[u8; 4]) -> bool {
         &self.checksum() == checksum
     }
+}
 
-    pub fn inner(&self) -> &str {
-        match self {
-            Self::NaturalByte { hash } => hash,
-            Self::ReverseByte { hash } => hash,
-        }
+impl NaturalBytes {
+    pub const fn to_reverse_bytes(self) -> ReverseBytes {
+        let mut bytes = self.0;
+        bytes.reverse();
+        ReverseBytes(bytes)
+    }
+}
+
+impl ReverseBytes {
+    pub const fn to_natural_bytes(self) -> NaturalBytes {
+        let mut bytes = self.0;
+        bytes.reverse();
+        NaturalBytes(bytes)
+    }
+}
+
+impl From<NaturalBytes> for Hash {
+    fn from(bytes: NaturalBytes) -> Self {
+        Self(bytes.0)
+    }
+}
+
+impl From<ReverseBytes> for Hash {
+    fn from(bytes: ReverseBytes) -> Self {
+        Self::from_reverse_bytes(bytes.0)
     }
+}
+
+impl AsRef<[u8]> for Hash {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Ord for Hash {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // The most significant byte is the last one.
+        self.0.iter().rev().cmp(other.0.iter().rev())
+    }
+}
+
+impl PartialOrd for Hash {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0.iter().rev() {
+            write!(f, "{:02x}", byte)?;
+        }
 
-    fn decode_to_bytes(hex: &str) -> [u8; 32] {
-        let mut out = [0u8; 32];
-        hex::decode_to_slice(hex, &mut out)
-            .expect("Failed to decode hash into bytes: Malformed hash");
+        Ok(())
+    }
+}
 
-        out
+impl fmt::Debug for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Hash({})", self)
     }
+}
 
-    fn reverse_hex(hex: &str) -> String {
-        hex.chars()
-            .collect::<Vec<_>>()
-            .chunks(2)
-            .rev()
-            .flat_map(|pair| {
-                if pair.len() == 2 {
-                    pair.to_vec()
-                } else {
-                    panic!("Failed to reverse hex String: Invalid hex String");
-                }
-            })
-            .collect()
+impl FromStr for Hash {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_reverse_hex(s)
     }
 }
 
 #[cfg(test)]
 mod hash_test {
-    use super::Hash256;
+    use crate::block::BlockHeaderBytes;
+
+    use super::{Hash, Hash256, HeaderHasher};
+
+    /// The mainnet genesis block header.
+    const GENESIS_HEADER: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
 
     #[test]
     fn hash256() {
@@ -143,7 +288,7 @@ mod hash_test {
         let hash = Hash256::digest(data);
         assert_eq!(
             "ef7391fd5ad3916f2e1c9d9df3b5e2adc546f63509c04ed9ec010cc880c96045",
-            hash.as_str()
+            hex::encode(hash.as_bytes())
         );
     }
 
@@ -151,6 +296,85 @@ mod hash_test {
     fn reverse_hex() {
         let data = b"My cool String!";
         let hash = Hash256::digest(data);
+        let reverse_hex = "4560c980c80c01ecd94ec00935f646c5ade2b5f39d9d1c2e6f91d35afd9173ef";
+
+        assert_eq!(reverse_hex, hash.to_string());
+        assert_eq!(hash, reverse_hex.parse::<Hash>().unwrap());
+        assert_eq!(
+            hash.reverse_bytes().to_natural_bytes(),
+            hash.natural_bytes()
+        );
+    }
+
+    #[test]
+    fn numeric_ordering() {
+        let mut low = [0u8; 32];
+        low[0] = 0xFF;
+        let mut high = [0u8; 32];
+        high[31] = 0x01;
+
+        assert!(Hash::from_natural_bytes(low) < Hash::from_natural_bytes(high));
+    }
+
+    #[test]
+    fn invalid_hex() {
+        assert!("00ff".parse::<Hash>().is_err());
+        assert!("zz".repeat(32).parse::<Hash>().is_err());
+    }
+
+    #[test]
+    fn header_hasher_genesis() {
+        let header = genesis_header_bytes();
+        let hasher = HeaderHasher::new(&header);
+
+        assert_eq!(
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+            hasher.hash().to_string()
+        );
+        assert_eq!(Hash256::digest(header.as_bytes()), hasher.hash());
+    }
+
+    #[test]
+    fn header_hasher_matches_hash256() {
+        let mut header = genesis_header_bytes();
+        let mut hasher = HeaderHasher::new(&header);
+        hasher.set_time(1_231_006_506);
+
+        for nonce in (0..u32::MAX).step_by(0x0F0F_0F0F) {
+            header.set_nonce(nonce);
+            let mut bytes = header.to_bytes();
+            bytes[68..72].copy_from_slice(&1_231_006_506u32.to_le_bytes());
+
+            let expected = Hash256::digest(&bytes);
+            assert_eq!(expected, hasher.hash_with_nonce(nonce));
+
+            hasher.set_nonce(nonce);
+            assert_eq!(expected, hasher.hash());
+        }
+    }
+
+    #[test]
+    fn header_hasher_set_version() {
+        let mut header = genesis_header_bytes();
+        let mut hasher = HeaderHasher::new(&header);
+
+        for version in [0x2000_0000, 0x3FFF_E000, 0x2000_2000] {
+            header.set_version(version);
+            hasher.set_version(version);
+
+            assert_eq!(Hash256::digest(header.as_bytes()), hasher.hash());
+            assert_eq!(
+                Hash256::digest(header.as_bytes()),
+                HeaderHasher::new(&header).hash()
+            );
+        }
+    }
+
+    fn genesis_header_bytes() -> BlockHeaderBytes {
+        let mut bytes = [0u8; 80];
+        hex::decode_to_slice(GENESIS_HEADER, &mut bytes).unwrap();
+
+        BlockHeaderBytes::new(bytes)
     }
 
     #[test]
@@ -0,0 +1,5 @@
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("invalid hash hex: {0}")]
+    InvalidHex(#[from] hex::FromHexError),
+}
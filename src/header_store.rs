@@ -0,0 +1,419 @@
+pub mod error;
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{
+    block::{BlockHeader, BlockHeaderBytes, HeaderEntry, HeaderTree, TipChange},
+    chain_params::ChainParams,
+    hash::{Hash, Hash256},
+    u256::U256,
+};
+
+use error::Error;
+
+/// # HeaderStore
+///
+/// A [`HeaderTree`] which keeps every inserted header on disk.
+///
+/// Headers are appended to a data file, one fixed-size record per header:
+/// ```plaintext
+/// 00000020...2c0a6b17 ................ Block header (80 bytes)
+/// 01000000 ........................... Height
+/// 0200000000...00 .................... Chainwork (32 bytes, little-endian)
+/// 3c1a8f02 ........................... Checksum: the first 4 bytes of Hash256 of the above
+/// ```
+///
+/// The file is only ever appended to.
+/// A record torn by a crash fails its checksum and is truncated on the next [`HeaderStore::open`].
+/// The genesis header is not stored, it comes from the [`ChainParams`].
+#[derive(Debug)]
+pub struct HeaderStore {
+    tree: HeaderTree,
+    data: BufWriter<File>,
+}
+
+impl HeaderStore {
+    pub const DATA_FILE_NAME: &str = "headers.dat";
+    const RECORD_SIZE: usize = 80 + 4 + 32 + 4;
+
+    /// Open or create the store in `dir` and load all stored headers.
+    pub fn open(dir: impl AsRef<Path>, params: &ChainParams) -> Result<Self, Error> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        let mut data = Self::open_file(&dir.join(Self::DATA_FILE_NAME))?;
+        let mut tree = HeaderTree::from_chain_params(params);
+
+        let records = Self::read_records(&mut data)?;
+        let count = records.len();
+
+        for (i, (header, height, chainwork)) in records.into_iter().enumerate() {
+            let record = i as u32;
+            let hash = header.hash();
+
+            tree.insert_hashed(header, hash)
+                .map_err(|_| Error::Corrupt {
+                    record,
+                    reason: "unknown previous header",
+                })?;
+
+            let entry = tree.get(&hash).expect("The header was just inserted.");
+            if entry.height != height || entry.chainwork != chainwork {
+                return Err(Error::Corrupt {
+                    record,
+                    reason: "height or chainwork mismatch",
+                });
+            }
+        }
+
+        log::info!(
+            "Loaded {} headers, best tip {} at height {}",
+            count,
+            tree.best_tip().hash,
+            tree.height()
+        );
+
+        Ok(Self {
+            tree,
+            data: BufWriter::new(data),
+        })
+    }
+
+    /// Validate `header` with [`HeaderTree::validate_header`], store it and insert it.
+    ///
+    /// Known headers are ignored.
+    pub fn insert(
+        &mut self,
+        header: BlockHeader,
+        params: &ChainParams,
+        now: u32,
+    ) -> Result<Option<TipChange>, Error> {
+        let mut changes = self.insert_headers([header], params, now)?;
+
+        Ok(changes.pop())
+    }
+
+    /// Validate, store and insert `headers` in order, then sync the files once.
+    ///
+    /// On an invalid header all headers before it stay stored.
+    /// If storing fails, none of `headers` stay in the tree.
+    /// Returns every change of the most-work tip.
+    pub fn insert_headers(
+        &mut self,
+        headers: impl IntoIterator<Item = BlockHeader>,
+        params: &ChainParams,
+        now: u32,
+    ) -> Result<Vec<TipChange>, Error> {
+        let old_tip = self.tree.best_tip().hash;
+        let mut inserted = Vec::new();
+        let mut changes = Vec::new();
+        let mut result = Ok(());
+        let mut written = Ok(());
+
+        for header in headers {
+            let hash = header.hash();
+            if self.tree.contains(&hash) {
+                continue;
+            }
+            let change = match self
+                .tree
+                .validate_header(&header, params, now)
+                .and_then(|()| self.tree.insert_hashed(header, hash))
+            {
+                Ok(change) => change,
+                Err(e) => {
+                    result = Err(e.into());
+                    break;
+                }
+            };
+            inserted.push(hash);
+            changes.extend(change);
+
+            written = self.append(&hash);
+            if written.is_err() {
+                break;
+            }
+        }
+
+        // Only headers synced to disk may stay in the tree.
+        if let Err(e) = written.and_then(|()| self.sync()) {
+            self.tree.remove_inserted(&inserted, old_tip);
+            return Err(e);
+        }
+
+        result.map(|()| changes)
+    }
+
+    pub fn tree(&self) -> &HeaderTree {
+        &self.tree
+    }
+
+    pub fn best_tip(&self) -> &HeaderEntry {
+        self.tree.best_tip()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.tree.height()
+    }
+
+    pub fn get(&self, hash: &Hash) -> Option<&HeaderEntry> {
+        self.tree.get(hash)
+    }
+
+    /// The header at `height` on the active chain.
+    pub fn get_by_height(&self, height: u32) -> Option<&HeaderEntry> {
+        self.tree.get_by_height(height)
+    }
+
+    /// Write all buffered records and sync them to disk.
+    pub fn sync(&mut self) -> Result<(), Error> {
+        self.data.flush()?;
+        self.data.get_ref().sync_data()?;
+
+        Ok(())
+    }
+
+    fn append(&mut self, hash: &Hash) -> Result<(), Error> {
+        let entry = self.tree.get(hash).expect("The header was inserted.");
+
+        let mut record = [0u8; Self::RECORD_SIZE];
+        record[..80].copy_from_slice(entry.header.as_bytes().as_bytes());
+        record[80..84].copy_from_slice(&entry.height.to_le_bytes());
+        record[84..116].copy_from_slice(&entry.chainwork.to_le_bytes());
+        let checksum = Hash256::digest(&record[..116]).checksum();
+        record[116..].copy_from_slice(&checksum);
+
+        self.data.write_all(&record)?;
+
+        Ok(())
+    }
+
+    fn open_file(path: &Path) -> Result<File, io::Error> {
+        OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+    }
+
+    /// Read all valid records and truncate the data file after the last one.
+    fn read_records(data: &mut File) -> Result<Vec<(BlockHeader, u32, U256)>, Error> {
+        let file_len = data.seek(SeekFrom::End(0))?;
+        data.seek(SeekFrom::Start(0))?;
+
+        let records = Self::decode_records(
+            &mut BufReader::new(&mut *data),
+            file_len as usize / Self::RECORD_SIZE,
+        )?;
+
+        let valid_len = (records.len() * Self::RECORD_SIZE) as u64;
+        if valid_len != file_len {
+            log::warn!(
+                "Truncating {} bytes of torn or corrupt header records",
+                file_len - valid_len
+            );
+            data.set_len(valid_len)?;
+        }
+
+        Ok(records)
+    }
+
+    /// Decode records until the end of `reader`, a torn record or a checksum mismatch.
+    ///
+    /// Other read errors are returned, so valid records are never truncated because of them.
+    fn decode_records(
+        reader: &mut impl Read,
+        capacity: usize,
+    ) -> Result<Vec<(BlockHeader, u32, U256)>, Error> {
+        let mut records = Vec::with_capacity(capacity);
+        let mut record = [0u8; Self::RECORD_SIZE];
+
+        loop {
+            match reader.read_exact(&mut record) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+
+            let checksum = Hash256::digest(&record[..116]).checksum();
+            if checksum != record[116..] {
+                break;
+            }
+
+            let header = BlockHeader::from_bytes(
+                &BlockHeaderBytes::try_from(&record[..80])
+                    .expect("The record has 80 header bytes."),
+            );
+            let height = u32::from_le_bytes(record[80..84].try_into().expect("4 bytes"));
+            let chainwork = U256::from_le_bytes(record[84..116].try_into().expect("32 bytes"));
+            records.push((header, height, chainwork));
+        }
+
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod header_store_test {
+    use std::{
+        fs::{self, OpenOptions},
+        io::{self, Read},
+    };
+
+    use crate::{
+        chain_params::ChainParams,
+        test_util::{TempDir, mine_chain},
+    };
+
+    use super::{HeaderStore, error::Error};
+
+    const NOW: u32 = 1_700_000_000;
+
+    #[test]
+    fn reload() {
+        let dir = TempDir::new("header_store_reload");
+        let params = &ChainParams::REGTEST;
+        let genesis = params.genesis_block().header().clone();
+
+        let main = mine_chain(&genesis, 5, NOW);
+        let fork = mine_chain(&main[1], 4, NOW + 100);
+        {
+            let mut store = HeaderStore::open(dir.path(), params).unwrap();
+            assert_eq!(0, store.height());
+
+            let changes = store.insert_headers(main.clone(), params, NOW).unwrap();
+            assert_eq!(5, changes.len());
+            assert_eq!(5, store.height());
+
+            assert_eq!(None, store.insert(fork[0].clone(), params, NOW).unwrap());
+            assert!(
+                store
+                    .insert(main[4].clone(), params, NOW)
+                    .unwrap()
+                    .is_none()
+            );
+        }
+
+        let mut store = HeaderStore::open(dir.path(), params).unwrap();
+        assert_eq!(5, store.height());
+        assert_eq!(main[4].hash(), store.best_tip().hash);
+        assert_eq!(main[2].hash(), store.get_by_height(3).unwrap().hash);
+        assert_eq!(3, store.get(&fork[0].hash()).unwrap().height);
+        assert_eq!(7, store.tree().len());
+
+        let changes = store
+            .insert_headers(fork[1..].to_vec(), params, NOW)
+            .unwrap();
+        assert!(changes.last().unwrap().is_reorg());
+        drop(store);
+
+        let store = HeaderStore::open(dir.path(), params).unwrap();
+        assert_eq!(6, store.height());
+        assert_eq!(fork[3].hash(), store.best_tip().hash);
+        assert_eq!(
+            fs::metadata(dir.path().join(HeaderStore::DATA_FILE_NAME))
+                .unwrap()
+                .len(),
+            9 * HeaderStore::RECORD_SIZE as u64
+        );
+    }
+
+    #[test]
+    fn torn_write() {
+        let dir = TempDir::new("header_store_torn");
+        let params = &ChainParams::REGTEST;
+        let genesis = params.genesis_block().header().clone();
+        let headers = mine_chain(&genesis, 3, NOW);
+
+        HeaderStore::open(dir.path(), params)
+            .unwrap()
+            .insert_headers(headers.clone(), params, NOW)
+            .unwrap();
+
+        // A crash in the middle of the last record.
+        let data_path = dir.path().join(HeaderStore::DATA_FILE_NAME);
+        let len = fs::metadata(&data_path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&data_path)
+            .unwrap()
+            .set_len(len - 7)
+            .unwrap();
+
+        let mut store = HeaderStore::open(dir.path(), params).unwrap();
+        assert_eq!(2, store.height());
+        assert_eq!(headers[1].hash(), store.best_tip().hash);
+        assert_eq!(
+            2 * HeaderStore::RECORD_SIZE as u64,
+            fs::metadata(&data_path).unwrap().len()
+        );
+
+        store.insert(headers[2].clone(), params, NOW).unwrap();
+        drop(store);
+        assert_eq!(3, HeaderStore::open(dir.path(), params).unwrap().height());
+    }
+
+    #[test]
+    fn invalid_headers() {
+        let dir = TempDir::new("header_store_invalid");
+        let params = &ChainParams::REGTEST;
+        let genesis = params.genesis_block().header().clone();
+        let mut headers = mine_chain(&genesis, 3, NOW);
+        headers[2].time = genesis.time;
+
+        let mut store = HeaderStore::open(dir.path(), params).unwrap();
+        assert!(matches!(
+            store.insert_headers(headers.clone(), params, NOW),
+            Err(Error::Block(_))
+        ));
+        drop(store);
+
+        // The valid headers before the invalid one are stored.
+        let store = HeaderStore::open(dir.path(), params).unwrap();
+        assert_eq!(2, store.height());
+
+        // A store of another network does not match the genesis header.
+        assert!(matches!(
+            HeaderStore::open(dir.path(), &ChainParams::MAINNET),
+            Err(Error::Corrupt { record: 0, .. })
+        ));
+    }
+
+    /// Fails every read, like a disk returning `EIO`.
+    struct FailingReader;
+
+    impl Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("EIO"))
+        }
+    }
+
+    #[test]
+    fn read_error() {
+        let dir = TempDir::new("header_store_read_error");
+        let params = &ChainParams::REGTEST;
+        let genesis = params.genesis_block().header().clone();
+
+        HeaderStore::open(dir.path(), params)
+            .unwrap()
+            .insert_headers(mine_chain(&genesis, 3, NOW), params, NOW)
+            .unwrap();
+        let bytes = fs::read(dir.path().join(HeaderStore::DATA_FILE_NAME)).unwrap();
+
+        assert_eq!(
+            3,
+            HeaderStore::decode_records(&mut bytes.as_slice(), 0)
+                .unwrap()
+                .len()
+        );
+        // Not mistaken for the end of the file.
+        assert!(matches!(
+            HeaderStore::decode_records(&mut bytes.as_slice().chain(FailingReader), 0),
+            Err(Error::IO(_))
+        ));
+    }
+}
@@ -0,0 +1,11 @@
+use crate::block;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("IO Error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("Block Error: {0}")]
+    Block(#[from] block::error::Error),
+    #[error("record {record} does not match the header chain: {reason}")]
+    Corrupt { record: u32, reason: &'static str },
+}
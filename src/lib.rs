@@ -1,7 +1,14 @@
-mod block;
-mod hash;
-mod minerr;
-mod networking;
+pub mod block;
+pub mod block_file;
+pub mod chain_params;
+pub mod encoding;
+pub mod hash;
+pub mod header_store;
+pub mod minerr;
+pub mod networking;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub mod u256;
 
 pub fn get_unix_timestamp() -> Result<std::time::Duration, std::time::SystemTimeError> {
     use std::time::{SystemTime, UNIX_EPOCH};
@@ -1,132 +1,375 @@
 use std::{
     collections::VecDeque,
-    io::{Read, Write},
+    hash::{BuildHasher, Hasher, RandomState},
+    io::{self, Write},
     net::{self, ToSocketAddrs},
-    sync::{Arc, Mutex},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+    },
     thread,
+    time::{Duration, Instant},
 };
 
-use crate::networking::{
-    header::Header, message::Message, payload::Payload, traits::NetworkInformation,
+use crate::{
+    chain_params::ChainParams,
+    get_unix_timestamp,
+    hash::Hash,
+    networking::{
+        command::Command,
+        frame::FrameDecoder,
+        inventory::{DEFAULT_REQUEST_TIMEOUT, Inventory, InventoryTracker, MAX_INV_SIZE},
+        message::Message,
+        payload::Payload,
+        ping::Ping,
+        traits::NetworkInformation,
+    },
 };
 
-mod command;
-mod error;
-mod header;
-mod message;
-mod payload;
-mod traits;
+pub mod command;
+pub mod error;
+pub mod frame;
+pub mod handshake;
+pub mod header;
+pub mod header_sync;
+pub mod inventory;
+pub mod message;
+pub mod payload;
+pub mod ping;
+pub mod traits;
+
+pub use handshake::{DEFAULT_HANDSHAKE_TIMEOUT, PeerInfo};
+pub use ping::{DEFAULT_PING_INTERVAL, DEFAULT_PING_TIMEOUT};
 
 pub const PROTOCOL_VERSION: u32 = 70015;
+pub const USER_AGENT: &str = concat!("/btc_minerr:", env!("CARGO_PKG_VERSION"), "/");
 pub const MAGIC_NUMBER_MAINNET: u32 = 0xF9BEB4D9;
 pub const MAGIC_NUMBER_REGTEST: u32 = 0xFABFB5DA;
 pub const MAGIC_NUMBER_TESTNET3: u32 = 0x0B110907;
+pub const MAGIC_NUMBER_TESTNET4: u32 = 0x1C163F28;
+pub const MAGIC_NUMBER_SIGNET: u32 = 0x0A03CF40;
 
 type ArcMutex<T> = Arc<Mutex<T>>;
 
 pub struct Network {
+    network_type: NetworkType,
+    peer_info: PeerInfo,
+    ping: ArcMutex<Ping>,
+    inventory: ArcMutex<InventoryTracker>,
+
     recv_queue: ArcMutex<VecDeque<Message>>,
-    send_queue: ArcMutex<VecDeque<Vec<u8>>>,
+    send_queue: mpsc::Sender<WriteCommand>,
+
+    /// Shut down on drop to stop the read worker.
+    stream: net::TcpStream,
+    /// Set on drop, the workers stop without logging a disconnect.
+    stop: Arc<AtomicBool>,
+    /// Taken to join the workers on drop.
+    read_worker: Option<thread::JoinHandle<()>>,
+    write_worker: Option<thread::JoinHandle<()>>,
+}
+
+/// What the write worker receives.
+enum WriteCommand {
+    /// A serialized message to send.
+    Send(Vec<u8>),
+    /// Recompute when the next ping or request expiry is due, e.g. after a pong.
+    Wake,
+}
 
-    read_worker: thread::JoinHandle<()>,
-    write_worker: thread::JoinHandle<()>,
+/// # ConnectOptions
+///
+/// The timeouts and the user agent of a [`Network`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectOptions {
+    /// How long connecting and the handshake may take each.
+    pub handshake_timeout: Duration,
+    /// How often to ping the peer.
+    pub ping_interval: Duration,
+    /// How long the peer may take to answer a ping before it is disconnected.
+    pub ping_timeout: Duration,
+    /// How long the peer may take to answer a request before it expires.
+    pub request_timeout: Duration,
+    /// Sent in our `version` message, see [`Payload::new_version_with_user_agent`].
+    pub user_agent: String,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum NetworkType {
     Mainnet,
-    Testnet,
+    Testnet3,
+    Testnet4,
+    /// The default signet.
+    Signet,
     Regtest,
 }
 
 impl Network {
-    /// Create a Network and connect to an address.
+    /// Create a Network, connect to an address and finish the handshake,
+    /// with the [`DEFAULT_HANDSHAKE_TIMEOUT`].
     pub fn connect<A>(addr: A, net_type: impl NetworkInformation) -> Result<Self, error::Error>
     where
         A: ToSocketAddrs,
     {
+        Self::connect_with_timeout(addr, net_type, DEFAULT_HANDSHAKE_TIMEOUT)
+    }
+
+    /// Create a Network, connect to an address and finish the handshake.
+    ///
+    /// The `timeout` applies to connecting and to the handshake each.
+    pub fn connect_with_timeout<A>(
+        addr: A,
+        net_type: impl NetworkInformation,
+        timeout: Duration,
+    ) -> Result<Self, error::Error>
+    where
+        A: ToSocketAddrs,
+    {
+        let options = ConnectOptions {
+            handshake_timeout: timeout,
+            ..Default::default()
+        };
+
+        Self::connect_with_options(addr, net_type, options)
+    }
+
+    /// Create a Network, connect to an address and finish the handshake.
+    ///
+    /// Afterwards the peer is pinged every [`ConnectOptions::ping_interval`]
+    /// and its pings are answered.
+    pub fn connect_with_options<A>(
+        addr: A,
+        net_type: impl NetworkInformation,
+        options: ConnectOptions,
+    ) -> Result<Self, error::Error>
+    where
+        A: ToSocketAddrs,
+    {
+        let network_type = NetworkType::try_from_magic_number(net_type.magic_number())
+            .ok_or(error::Error::UnknownMagic(net_type.magic_bytes()))?;
+
         log::info!("Connecting...");
 
-        let read_stream = net::TcpStream::connect(addr)?;
+        let read_stream = Self::connect_stream(addr, options.handshake_timeout)?;
+        let mut decoder = FrameDecoder::new(network_type);
+        let peer_info = handshake::handshake(
+            &read_stream,
+            &mut decoder,
+            options.handshake_timeout,
+            &options.user_agent,
+        )?;
         let write_stream = read_stream.try_clone().expect("Failed to clone TcpStream");
+        let stream = read_stream.try_clone().expect("Failed to clone TcpStream");
+        let stop = Arc::new(AtomicBool::new(false));
 
         let recv_queue = Arc::new(Mutex::new(VecDeque::with_capacity(16)));
-        let send_queue = Arc::new(Mutex::new(VecDeque::with_capacity(16)));
+        let (send_queue, send_queue_rx) = mpsc::channel();
 
-        let recf_queue_c = recv_queue.clone();
-        let read_worker = thread::spawn(move || Self::read_worker(read_stream, recf_queue_c));
+        let ping = Arc::new(Mutex::new(Ping::new(
+            options.ping_interval,
+            options.ping_timeout,
+        )));
+
+        let inventory = Arc::new(Mutex::new(InventoryTracker::new(options.request_timeout)));
 
+        let recf_queue_c = recv_queue.clone();
         let send_queue_c = send_queue.clone();
-        let write_worker = thread::spawn(move || Self::write_worker(write_stream, send_queue_c));
+        let ping_c = ping.clone();
+        let inventory_c = inventory.clone();
+        let stop_c = stop.clone();
+        let read_worker = thread::spawn(move || {
+            Self::read_worker(
+                read_stream,
+                decoder,
+                recf_queue_c,
+                send_queue_c,
+                ping_c,
+                inventory_c,
+                stop_c,
+            )
+        });
+
+        let ping_c = ping.clone();
+        let inventory_c = inventory.clone();
+        let stop_c = stop.clone();
+        let write_worker = thread::spawn(move || {
+            Self::write_worker(
+                write_stream,
+                network_type,
+                send_queue_rx,
+                ping_c,
+                inventory_c,
+                stop_c,
+            )
+        });
 
         Ok(Self {
+            network_type,
+            peer_info,
+            ping,
+            inventory,
+
             recv_queue,
             send_queue,
 
-            read_worker,
-            write_worker,
+            stream,
+            stop,
+            read_worker: Some(read_worker),
+            write_worker: Some(write_worker),
         })
     }
 
-    fn handshake(mut read_stream: &net::TcpStream) -> Result<(), error::Error> {
-        todo!();
-    }
-
-    fn read_worker(mut read_stream: net::TcpStream, recv_queue: ArcMutex<VecDeque<Message>>) {
-        Self::handshake(&read_stream).expect("Failed to finish handshake.");
+    /// Connect to the first address which accepts the connection.
+    fn connect_stream(
+        addr: impl ToSocketAddrs,
+        timeout: Duration,
+    ) -> Result<net::TcpStream, error::Error> {
+        let mut last_error = None;
+        for addr in addr.to_socket_addrs()? {
+            match net::TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = Some(e),
+            }
+        }
 
-        let mut header_bytes = [0u8; 24];
-        let mut payload: Vec<u8> = Vec::new();
+        Err(last_error
+            .unwrap_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "No address to connect to.")
+            })
+            .into())
+    }
 
+    /// Receive messages until the peer disconnects or sends an invalid message,
+    /// which disconnects the peer, or until the [`Network`] is dropped.
+    ///
+    /// Pings are answered and pongs are recorded, both are not queued.
+    /// A pong wakes the write worker, the next ping is due an interval after the last one.
+    /// Announced, received and not found inventory is tracked.
+    fn read_worker(
+        mut read_stream: net::TcpStream,
+        mut decoder: FrameDecoder,
+        recv_queue: ArcMutex<VecDeque<Message>>,
+        send_queue: mpsc::Sender<WriteCommand>,
+        ping: ArcMutex<Ping>,
+        inventory: ArcMutex<InventoryTracker>,
+        stop: Arc<AtomicBool>,
+    ) {
         loop {
-            read_stream
-                .read_exact(&mut header_bytes)
-                .expect("Failed to read header.");
-            let header = Header::from_bytes(&header_bytes);
-
-            payload.resize(header.size() as usize, 0);
-            read_stream
-                .read_exact(&mut payload)
-                .expect("Failed to read payload.");
+            let message = match decoder.read_message(&mut read_stream) {
+                Ok(message) => message,
+                Err(_) if stop.load(Ordering::Relaxed) => return,
+                Err(e) => {
+                    log::warn!("Disconnecting: {}", e);
+                    let _ = read_stream.shutdown(net::Shutdown::Both);
+
+                    return;
+                }
+            };
 
-            Self::process_payload(&recv_queue, header, &payload);
+            match message.payload() {
+                Payload::Ping(nonce) => {
+                    let pong = Message::from_payload(
+                        decoder.network_type(),
+                        Command::Pong,
+                        Payload::Pong(*nonce),
+                    );
+                    let _ = send_queue.send(WriteCommand::Send(pong.to_bytes()));
+                }
+                Payload::Pong(nonce) => {
+                    ping.lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .pong(*nonce, Instant::now());
+                    let _ = send_queue.send(WriteCommand::Wake);
+                }
+                payload => {
+                    Self::track_inventory(
+                        &mut inventory
+                            .lock()
+                            .unwrap_or_else(|poisoned| poisoned.into_inner()),
+                        payload,
+                    );
+                    recv_queue
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .push_back(message);
+                }
+            }
         }
     }
 
-    fn process_payload(recv_queue: &ArcMutex<VecDeque<Message>>, header: Header, data: &[u8]) {
-        let payload = match Payload::from_bytes(&header, data) {
-            Ok(payload) => payload,
-            Err(e) => match e {
-                error::Error::ChecksumMismatch => {
-                    log::warn!("Payload mismatch!");
-                    Payload::ChecksumMismatch(data.to_vec())
+    fn track_inventory(tracker: &mut InventoryTracker, payload: &Payload) {
+        match payload {
+            Payload::Inv(inventory) => tracker.announce(inventory),
+            Payload::NotFound(inventory) => {
+                for inv in inventory {
+                    tracker.complete(&inv.hash);
                 }
-                _ => panic!("Unknown error on `Payload::from_bytes()` function!"),
-            },
-        };
-
-        let message = Message::new(header, payload);
-
-        recv_queue
-            .lock()
-            .unwrap_or_else(|poisoned| poisoned.into_inner())
-            .push_back(message);
+            }
+            Payload::Block(block) => tracker.complete(&block.block_header_hash256()),
+            Payload::Tx(transaction) => {
+                tracker.complete(&transaction.txid());
+                tracker.complete(&transaction.wtxid());
+            }
+            _ => {}
+        }
     }
 
-    fn write_worker(mut write_stream: net::TcpStream, send_queue: ArcMutex<VecDeque<Vec<u8>>>) {
-        loop {
-            if let Some(msg) = send_queue
-                .lock()
-                .unwrap_or_else(|poisoned| poisoned.into_inner())
-                .pop_front()
-            {
-                match write_stream.write_all(&msg) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        log::error!("Failed to write message: {}", e);
+    /// Send the queued messages and the pings,
+    /// until writing fails or the peer does not answer a ping, which disconnects the peer,
+    /// or until the [`Network`] is dropped.
+    ///
+    /// Expires the requests the peer did not answer in time.
+    /// Between messages it waits until the next ping or request expiry is due.
+    fn write_worker(
+        mut write_stream: net::TcpStream,
+        network_type: NetworkType,
+        send_queue: mpsc::Receiver<WriteCommand>,
+        ping: ArcMutex<Ping>,
+        inventory: ArcMutex<InventoryTracker>,
+        stop: Arc<AtomicBool>,
+    ) {
+        while !stop.load(Ordering::Relaxed) {
+            let now = Instant::now();
+            let next_expiry = {
+                let mut inventory = inventory
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                inventory.expire(now);
+                inventory.next_expiry()
+            };
+
+            let (ping_nonce, next_ping) = {
+                let mut ping = ping.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                (ping.poll(now), ping.next_poll(now))
+            };
+            let msg = match ping_nonce {
+                Ok(Some(nonce)) => {
+                    Message::from_payload(network_type, Command::Ping, Payload::Ping(nonce))
+                        .to_bytes()
+                }
+                Ok(None) => {
+                    let deadline = next_expiry.map_or(next_ping, |expiry| expiry.min(next_ping));
+                    match send_queue.recv_timeout(deadline.saturating_duration_since(now)) {
+                        Ok(WriteCommand::Send(msg)) => msg,
+                        Ok(WriteCommand::Wake) | Err(RecvTimeoutError::Timeout) => continue,
+                        Err(RecvTimeoutError::Disconnected) => return,
                     }
-                };
+                }
+                Err(e) => {
+                    log::warn!("Disconnecting: {}", e);
+                    let _ = write_stream.shutdown(net::Shutdown::Both);
+
+                    return;
+                }
             };
+
+            if let Err(e) = write_stream.write_all(&msg) {
+                log::error!("Failed to write message: {}", e);
+                let _ = write_stream.shutdown(net::Shutdown::Both);
+
+                return;
+            }
         }
     }
 
@@ -135,10 +378,65 @@ impl Network {
     ///
     /// This operates on a FIFO (first-in-first-out) queue.
     pub fn send(&mut self, message: Message) {
-        self.send_queue
+        // Only fails after the write worker stopped.
+        let _ = self.send_queue.send(WriteCommand::Send(message.to_bytes()));
+    }
+
+    /// Send `payload` as `command`, see [`Self::send`].
+    pub fn send_payload(&mut self, command: Command, payload: Payload) {
+        self.send(Message::from_payload(self.network_type, command, payload));
+    }
+
+    /// Request `inventory` from the peer with `getdata`.
+    ///
+    /// The objects arrive as [`Payload::Block`] or [`Payload::Tx`],
+    /// or are listed in a [`Payload::NotFound`].
+    /// Requests not answered within [`ConnectOptions::request_timeout`] expire,
+    /// see [`Self::take_expired_requests`].
+    ///
+    /// Inventory already in flight is not requested again.
+    /// Returns the inventory which is not requested,
+    /// because [`inventory::MAX_IN_FLIGHT`] requests are in flight.
+    pub fn request(&mut self, inventory: Vec<Inventory>) -> Vec<Inventory> {
+        let (send, over_limit) = self
+            .inventory
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .request(inventory, Instant::now());
+
+        for chunk in send.chunks(MAX_INV_SIZE) {
+            self.send_payload(Command::GetData, Payload::GetData(chunk.to_vec()));
+        }
+
+        over_limit
+    }
+
+    /// The hashes of the requests which expired since the last call,
+    /// to request them from another peer.
+    pub fn take_expired_requests(&mut self) -> Vec<Hash> {
+        self.inventory
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take_expired()
+    }
+
+    /// When `hash` was requested with [`Self::request`],
+    /// if it was neither received nor listed as not found since and did not expire.
+    pub fn requested_at(&self, hash: &Hash) -> Option<Instant> {
+        self.inventory
             .lock()
             .unwrap_or_else(|poisoned| poisoned.into_inner())
-            .push_back(message.to_bytes());
+            .requested_at(hash)
+    }
+
+    /// Whether the peer announced `hash` with an `inv`.
+    ///
+    /// Only the last [`inventory::MAX_ANNOUNCED`] announcements are remembered.
+    pub fn has_announced(&self, hash: &Hash) -> bool {
+        self.inventory
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .has_announced(hash)
     }
 
     /// Get the oldest received unread [`BtcMessage`].
@@ -151,6 +449,38 @@ impl Network {
             .pop_front()
     }
 
+    pub fn network_type(&self) -> NetworkType {
+        self.network_type
+    }
+
+    /// What the peer told us about itself in the handshake.
+    pub fn peer_info(&self) -> &PeerInfo {
+        &self.peer_info
+    }
+
+    /// The round-trip time of the last answered ping.
+    pub fn latency(&self) -> Option<Duration> {
+        self.ping
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .latency()
+    }
+
+    /// The lowest round-trip time of all answered pings.
+    pub fn min_latency(&self) -> Option<Duration> {
+        self.ping
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .min_latency()
+    }
+
+    /// Whether the read and write workers are still running.
+    pub fn is_connected(&self) -> bool {
+        [&self.read_worker, &self.write_worker]
+            .into_iter()
+            .all(|worker| worker.as_ref().is_some_and(|worker| !worker.is_finished()))
+    }
+
     /// Get the current received count of [`BtcMessage`].
     ///
     /// This can always change and the count of messages may be outdated directly after this function returns.
@@ -162,40 +492,109 @@ impl Network {
     }
 }
 
-impl NetworkType {
-    pub fn from_magic_bytes(magic_bytes: &[u8; 4]) -> Self {
-        Self::from_magic_number(u32::from_ne_bytes(*magic_bytes))
+/// Disconnect from the peer and wait for the workers to stop.
+impl Drop for Network {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.send_queue.send(WriteCommand::Wake);
+        // Wakes the read worker.
+        let _ = self.stream.shutdown(net::Shutdown::Both);
+
+        for worker in [self.read_worker.take(), self.write_worker.take()]
+            .into_iter()
+            .flatten()
+        {
+            if worker.join().is_err() {
+                log::error!("A network worker panicked.");
+            }
+        }
     }
+}
 
-    pub fn from_magic_number(magic_number: u32) -> Self {
-        match magic_number {
-            MAGIC_NUMBER_MAINNET => Self::Mainnet,
-            MAGIC_NUMBER_REGTEST => Self::Regtest,
-            MAGIC_NUMBER_TESTNET3 => Self::Testnet,
-            _ => panic!("Unknown magic number!"),
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self {
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+            ping_interval: DEFAULT_PING_INTERVAL,
+            ping_timeout: DEFAULT_PING_TIMEOUT,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            user_agent: USER_AGENT.to_string(),
         }
     }
 }
 
+impl NetworkType {
+    pub const ALL: [Self; 5] = [
+        Self::Mainnet,
+        Self::Testnet3,
+        Self::Testnet4,
+        Self::Signet,
+        Self::Regtest,
+    ];
+
+    /// The network of `magic_bytes`, [`None`] for unknown ones, e.g. sent by a peer.
+    pub fn try_from_magic_bytes(magic_bytes: &[u8; 4]) -> Option<Self> {
+        Self::try_from_magic_number(u32::from_be_bytes(*magic_bytes))
+    }
+
+    pub fn try_from_magic_number(magic_number: u32) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|network| network.magic_number() == magic_number)
+    }
+
+    pub const fn chain_params(&self) -> &'static ChainParams {
+        ChainParams::from_network(*self)
+    }
+}
+
+/// A nonce for pings and to detect connections to ourselves, it does not need to be secure.
+fn random_nonce() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(timestamp) = get_unix_timestamp() {
+        hasher.write_u128(timestamp.as_nanos());
+    }
+
+    hasher.finish()
+}
+
 impl NetworkInformation for NetworkType {
     fn port(&self) -> u16 {
-        match self {
-            Self::Mainnet => 8333,
-            Self::Regtest => 18333,
-            Self::Testnet => 18444,
-        }
+        self.chain_params().port()
     }
 
     fn magic_bytes(&self) -> [u8; 4] {
-        // The magic number is in littel-endian. We do not want to convert it again.
-        self.magic_number().to_ne_bytes()
+        self.chain_params().magic_bytes()
     }
 
     fn magic_number(&self) -> u32 {
-        match self {
-            Self::Mainnet => MAGIC_NUMBER_MAINNET,
-            Self::Regtest => MAGIC_NUMBER_REGTEST,
-            Self::Testnet => MAGIC_NUMBER_TESTNET3,
-        }
+        self.chain_params().magic_number()
+    }
+}
+
+#[cfg(test)]
+mod networking_test {
+    use std::{io::Read, sync::mpsc, time::Duration};
+
+    use crate::{
+        networking::{Network, NetworkType},
+        test_util::spawn_handshake_peer,
+    };
+
+    #[test]
+    fn disconnect_on_drop() {
+        let (closed_tx, closed_rx) = mpsc::channel();
+        let addr = spawn_handshake_peer(move |mut stream, _| {
+            // Read until the network closes the connection.
+            let mut buf = [0u8; 1024];
+            while stream.read(&mut buf).is_ok_and(|len| len > 0) {}
+            closed_tx.send(()).unwrap();
+        });
+
+        let network = Network::connect(addr, NetworkType::Regtest).unwrap();
+        assert!(network.is_connected());
+
+        drop(network);
+        closed_rx.recv_timeout(Duration::from_secs(5)).unwrap();
     }
 }
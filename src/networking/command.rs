@@ -1,3 +1,7 @@
+use std::io::{self, Write};
+
+use crate::{encoding::Encodable, networking::error};
+
 pub type CommandBytes = [u8; 12];
 
 pub const VERSION_BYTES: CommandBytes = [
@@ -7,25 +11,167 @@ pub const VERACK_BYTES: CommandBytes = [
     0x76, 0x65, 0x72, 0x61, 0x63, 0x6B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
 ];
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum Command {
-    Version,
-    Verack,
+macro_rules! commands {
+    ($($variant:ident => $name:literal,)*) => {
+        /// # Command
+        ///
+        /// The command name of a message, ASCII padded with zero bytes to 12 bytes.
+        ///
+        /// Commands this crate does not know are kept as [`Command::Unknown`],
+        /// so new commands of peers are passed through instead of dropping the connection.
+        ///
+        /// See [Message types](https://en.bitcoin.it/wiki/Protocol_documentation#Message_types).
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Command {
+            $($variant,)*
+            Unknown(CommandBytes),
+        }
+
+        impl Command {
+            /// All known commands.
+            pub const ALL: &[Self] = &[$(Self::$variant,)*];
+
+            /// The name without padding, [`None`] for [`Self::Unknown`].
+            pub const fn name(&self) -> Option<&'static str> {
+                match self {
+                    $(Self::$variant => Some($name),)*
+                    Self::Unknown(_) => None,
+                }
+            }
+
+            fn from_name(name: &[u8]) -> Option<Self> {
+                match name {
+                    $(_ if name == $name.as_bytes() => Some(Self::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+commands! {
+    Version => "version",
+    Verack => "verack",
+    Addr => "addr",
+    AddrV2 => "addrv2",
+    SendAddrV2 => "sendaddrv2",
+    Inv => "inv",
+    GetData => "getdata",
+    NotFound => "notfound",
+    GetBlocks => "getblocks",
+    GetHeaders => "getheaders",
+    Tx => "tx",
+    Block => "block",
+    Headers => "headers",
+    SendHeaders => "sendheaders",
+    GetAddr => "getaddr",
+    Mempool => "mempool",
+    Ping => "ping",
+    Pong => "pong",
+    Reject => "reject",
+    FilterLoad => "filterload",
+    FilterAdd => "filteradd",
+    FilterClear => "filterclear",
+    MerkleBlock => "merkleblock",
+    SendCmpct => "sendcmpct",
+    CmpctBlock => "cmpctblock",
+    GetBlockTxn => "getblocktxn",
+    BlockTxn => "blocktxn",
+    FeeFilter => "feefilter",
+    WtxidRelay => "wtxidrelay",
+    GetCFilters => "getcfilters",
+    CFilter => "cfilter",
+    GetCFHeaders => "getcfheaders",
+    CFHeaders => "cfheaders",
+    GetCFCheckpt => "getcfcheckpt",
+    CFCheckpt => "cfcheckpt",
 }
 
 impl Command {
-    pub fn from_bytes(bytes: &CommandBytes) -> Self {
-        match bytes {
-            &VERSION_BYTES => Self::Version,
-            &VERACK_BYTES => Self::Verack,
-            _ => panic!("Unknown command: {:?}", bytes),
+    /// Decode a command name.
+    ///
+    /// The same as Bitcoin Core, the name has to be printable ASCII followed by only zero bytes.
+    /// Valid but unknown names become [`Self::Unknown`].
+    pub fn from_bytes(bytes: &CommandBytes) -> Result<Self, error::Error> {
+        let len = bytes.iter().position(|byte| *byte == 0).unwrap_or(12);
+        let (name, padding) = bytes.split_at(len);
+
+        if !name.iter().all(|byte| (0x20..0x7F).contains(byte))
+            || padding.iter().any(|byte| *byte != 0)
+        {
+            return Err(error::Error::InvalidCommand(*bytes));
         }
+
+        Ok(Self::from_name(name).unwrap_or(Self::Unknown(*bytes)))
+    }
+
+    pub const fn to_bytes(&self) -> CommandBytes {
+        let name = match self {
+            Self::Unknown(bytes) => return *bytes,
+            command => match command.name() {
+                Some(name) => name.as_bytes(),
+                None => unreachable!(),
+            },
+        };
+
+        let mut bytes = [0u8; 12];
+        let mut i = 0;
+        while i < name.len() {
+            bytes[i] = name[i];
+            i += 1;
+        }
+
+        bytes
+    }
+}
+
+impl Encodable for Command {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        self.to_bytes().consensus_encode(writer)
+    }
+}
+
+#[cfg(test)]
+mod command_test {
+    use crate::networking::{
+        command::{Command, CommandBytes, VERACK_BYTES, VERSION_BYTES},
+        error::Error,
+    };
+
+    #[test]
+    fn from_to_bytes() {
+        assert_eq!(VERSION_BYTES, Command::Version.to_bytes());
+        assert_eq!(VERACK_BYTES, Command::Verack.to_bytes());
+
+        for command in Command::ALL {
+            assert!(command.name().unwrap().len() <= 12);
+            assert_eq!(*command, Command::from_bytes(&command.to_bytes()).unwrap());
+        }
+
+        let unknown: CommandBytes = *b"utreexo\0\0\0\0\0";
+        assert_eq!(
+            Command::Unknown(unknown),
+            Command::from_bytes(&unknown).unwrap()
+        );
+        assert_eq!(unknown, Command::Unknown(unknown).to_bytes());
+        assert_eq!(None, Command::Unknown(unknown).name());
+
+        // Names of all 12 bytes do not need padding.
+        assert!(Command::from_bytes(b"abcdefghijkl").is_ok());
     }
 
-    pub const fn to_bytes(&self) -> [u8; 12] {
-        match self {
-            Self::Version => VERSION_BYTES,
-            Self::Verack => VERACK_BYTES,
+    #[test]
+    fn invalid() {
+        for bytes in [
+            *b"ping\0\0\0\0\0\0\0x",
+            *b"ping\0pong\0\0\0",
+            *b"p\x01ng\0\0\0\0\0\0\0\0",
+            *b"p\xFFng\0\0\0\0\0\0\0\0",
+        ] {
+            assert!(matches!(
+                Command::from_bytes(&bytes),
+                Err(Error::InvalidCommand(invalid)) if invalid == bytes
+            ));
         }
     }
 }
@@ -4,6 +4,40 @@ use thiserror::Error;
 pub enum Error {
     #[error("IO Error: {0}")]
     IO(#[from] std::io::Error),
+    #[error("Decode Error: {0}")]
+    Decode(#[from] crate::encoding::error::Error),
     #[error("Checksum mismatch")]
     ChecksumMismatch,
+    #[error("invalid command name {}", hex::encode(.0))]
+    InvalidCommand([u8; 12]),
+    #[error("the user agent is {0} bytes, more than allowed")]
+    UserAgentTooLong(u64),
+    #[error("the user agent has to be ASCII")]
+    NonAsciiUserAgent,
+    #[error("unknown network magic bytes {}", hex::encode(.0))]
+    UnknownMagic([u8; 4]),
+    #[error("expected magic bytes {} but got {}", hex::encode(.expected), hex::encode(.actual))]
+    UnexpectedMagic { expected: [u8; 4], actual: [u8; 4] },
+    #[error("the message is {0} bytes, more than allowed")]
+    OversizedMessage(u32),
+    #[error("the handshake did not finish in time")]
+    HandshakeTimeout,
+    #[error("Block Error: {0}")]
+    Block(#[from] crate::block::error::Error),
+    #[error("Header Store Error: {0}")]
+    HeaderStore(#[from] crate::header_store::error::Error),
+    #[error("{0} headers in one message, more than allowed")]
+    TooManyHeaders(u64),
+    #[error("{0} inventory vectors in one message, more than allowed")]
+    TooManyInventory(u64),
+    #[error("{0} hashes in a block locator, more than allowed")]
+    LocatorTooLong(usize),
+    #[error("the peer did not answer in time")]
+    ResponseTimeout,
+    #[error("the peer disconnected")]
+    Disconnected,
+    #[error("the peer did not answer our ping in time")]
+    PingTimeout,
+    #[error("connected to ourselves")]
+    SelfConnection,
 }
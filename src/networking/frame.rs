@@ -0,0 +1,196 @@
+use std::io::{self, Read};
+
+use crate::networking::{
+    NetworkType, error::Error, header::Header, message::Message, payload::Payload,
+    traits::NetworkInformation,
+};
+
+/// The largest payload we accept, the same as Bitcoin Core´s `MAX_SIZE`.
+pub const MAX_MESSAGE_SIZE: u32 = 0x0200_0000;
+
+/// # FrameDecoder
+///
+/// Splits a stream of bytes into messages.
+///
+/// Bytes are buffered until a whole message arrived, so a message may come in any number of reads.
+/// The size of a message is checked before its payload is buffered,
+/// so a peer can not make us allocate more than [`MAX_MESSAGE_SIZE`].
+///
+/// After an error the stream can not be resynchronized and the peer should be disconnected.
+#[derive(Debug)]
+pub struct FrameDecoder {
+    network_type: NetworkType,
+    buffer: Vec<u8>,
+}
+
+impl FrameDecoder {
+    const READ_SIZE: usize = 8 * 1024;
+
+    /// A decoder accepting only messages of `network_type`.
+    pub fn new(network_type: NetworkType) -> Self {
+        Self {
+            network_type,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn network_type(&self) -> NetworkType {
+        self.network_type
+    }
+
+    /// Append received bytes.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// The count of buffered bytes which are not part of a returned message yet.
+    pub fn buffered_len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// The next whole message in the buffer, or [`None`] if more bytes are needed.
+    pub fn next_message(&mut self) -> Result<Option<Message>, Error> {
+        if self.buffer.len() < Header::SIZE {
+            return Ok(None);
+        }
+
+        let magic_bytes = [
+            self.buffer[0],
+            self.buffer[1],
+            self.buffer[2],
+            self.buffer[3],
+        ];
+        if magic_bytes != self.network_type.magic_bytes() {
+            return Err(Error::UnexpectedMagic {
+                expected: self.network_type.magic_bytes(),
+                actual: magic_bytes,
+            });
+        }
+
+        let header = Header::from_bytes(&self.buffer[..Header::SIZE])?;
+        if header.size() > MAX_MESSAGE_SIZE {
+            return Err(Error::OversizedMessage(header.size()));
+        }
+
+        let end = Header::SIZE + header.size() as usize;
+        if self.buffer.len() < end {
+            return Ok(None);
+        }
+
+        let payload = Payload::from_bytes(&header, &self.buffer[Header::SIZE..end]);
+        self.buffer.drain(..end);
+
+        Ok(Some(Message::new(header, payload?)))
+    }
+
+    /// Read from `reader` until a whole message arrived.
+    ///
+    /// Bytes read after the message stay buffered for the next call.
+    pub fn read_message(&mut self, reader: &mut impl Read) -> Result<Message, Error> {
+        let mut chunk = [0u8; Self::READ_SIZE];
+
+        loop {
+            if let Some(message) = self.next_message()? {
+                return Ok(message);
+            }
+
+            match reader.read(&mut chunk) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(read) => self.push(&chunk[..read]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod frame_test {
+    use std::io;
+
+    use crate::networking::{
+        NetworkType,
+        command::Command,
+        error::Error,
+        frame::{FrameDecoder, MAX_MESSAGE_SIZE},
+        header::Header,
+        message::Message,
+        payload::Payload,
+    };
+
+    fn ping(nonce: u64) -> Vec<u8> {
+        Message::from_payload(NetworkType::Mainnet, Command::Ping, Payload::Ping(nonce)).to_bytes()
+    }
+
+    #[test]
+    fn split_messages() {
+        let mut bytes = ping(1);
+        bytes.extend_from_slice(&ping(2));
+        bytes.extend_from_slice(&ping(3)[..10]);
+
+        // Byte by byte.
+        let mut decoder = FrameDecoder::new(NetworkType::Mainnet);
+        let mut messages = Vec::new();
+        for byte in &bytes {
+            decoder.push(&[*byte]);
+            messages.extend(decoder.next_message().unwrap());
+        }
+        assert_eq!(2, messages.len());
+        assert_eq!(10, decoder.buffered_len());
+
+        // From a reader, in one read.
+        let mut decoder = FrameDecoder::new(NetworkType::Mainnet);
+        let mut reader = bytes.as_slice();
+        for nonce in [1u64, 2] {
+            let message = decoder.read_message(&mut reader).unwrap();
+            assert_eq!(Command::Ping, message.header().command());
+            assert!(matches!(message.payload(), Payload::Ping(n) if *n == nonce));
+        }
+        assert!(matches!(
+            decoder.read_message(&mut reader),
+            Err(Error::IO(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+        ));
+    }
+
+    #[test]
+    fn bad_frames() {
+        // Another network.
+        let mut decoder = FrameDecoder::new(NetworkType::Testnet4);
+        decoder.push(&ping(1));
+        assert!(matches!(
+            decoder.next_message(),
+            Err(Error::UnexpectedMagic {
+                expected: [0x1C, 0x16, 0x3F, 0x28],
+                actual: [0xF9, 0xBE, 0xB4, 0xD9]
+            })
+        ));
+
+        // Oversized, rejected before the payload arrived.
+        let mut bytes = ping(1);
+        bytes[16..20].copy_from_slice(&(MAX_MESSAGE_SIZE + 1).to_le_bytes());
+        let mut decoder = FrameDecoder::new(NetworkType::Mainnet);
+        decoder.push(&bytes[..Header::SIZE]);
+        assert!(matches!(
+            decoder.next_message(),
+            Err(Error::OversizedMessage(size)) if size == MAX_MESSAGE_SIZE + 1
+        ));
+
+        let mut bytes = ping(1);
+        bytes[Header::SIZE] ^= 1;
+        let mut decoder = FrameDecoder::new(NetworkType::Mainnet);
+        decoder.push(&bytes);
+        assert!(matches!(
+            decoder.next_message(),
+            Err(Error::ChecksumMismatch)
+        ));
+
+        let mut bytes = ping(1);
+        bytes[4] = 0xFF;
+        let mut decoder = FrameDecoder::new(NetworkType::Mainnet);
+        decoder.push(&bytes);
+        assert!(matches!(
+            decoder.next_message(),
+            Err(Error::InvalidCommand(_))
+        ));
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    io::{self, Write},
+    net,
+    time::{Duration, Instant},
+};
+
+use crate::networking::{
+    PROTOCOL_VERSION, command::Command, error::Error, frame::FrameDecoder, message::Message,
+    payload::Payload, random_nonce,
+};
+
+/// How long to wait for the peer to finish the handshake by default, the same as Bitcoin Core.
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// # PeerInfo
+///
+/// What a peer told us about itself in its `version` message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerInfo {
+    pub addr: net::SocketAddr,
+    /// The lower of our and the peer´s protocol version.
+    pub version: u32,
+    /// A bit field.
+    pub services: u64,
+    pub user_agent: String,
+    /// The height of the peer´s best block.
+    pub start_height: u32,
+    /// Whether the peer wants to receive transactions before sending a filter (BIP37).
+    pub relay: bool,
+}
+
+/// # Handshake
+///
+/// ```plaintext
+/// us                    peer
+///  | ---- version ----> |
+///  | <---- version ---- |
+///  | ----- verack ----> |
+///  | <---- verack ----- |
+/// ```
+///
+/// The peer´s `version` and `verack` may arrive in any order.
+/// A `version` with our own nonce means we connected to ourselves.
+///
+/// `timeout` applies to the whole handshake.
+/// Errors before sending anything if `user_agent` is invalid.
+///
+/// Messages after the handshake may already be read, they stay buffered in `decoder`.
+///
+/// See [Version Handshake](https://en.bitcoin.it/wiki/Version_Handshake).
+pub(super) fn handshake(
+    mut stream: &net::TcpStream,
+    decoder: &mut FrameDecoder,
+    timeout: Duration,
+    user_agent: &str,
+) -> Result<PeerInfo, Error> {
+    let network_type = decoder.network_type();
+    let deadline = Instant::now() + timeout;
+    let nonce = random_nonce();
+
+    let version = Payload::new_version_with_user_agent(
+        stream.peer_addr()?,
+        stream.local_addr()?,
+        nonce,
+        user_agent,
+    )?;
+    stream.write_all(&Message::from_payload(network_type, Command::Version, version).to_bytes())?;
+
+    let mut peer_info = None;
+    let mut verack = false;
+
+    while peer_info.is_none() || !verack {
+        let remaining = deadline
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero())
+            .ok_or(Error::HandshakeTimeout)?;
+        stream.set_read_timeout(Some(remaining))?;
+
+        let message = match decoder.read_message(&mut stream) {
+            Err(Error::IO(e) | Error::Decode(crate::encoding::error::Error::IO(e)))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Err(Error::HandshakeTimeout);
+            }
+            message => message?,
+        };
+
+        match message.payload() {
+            Payload::Version {
+                version,
+                services,
+                nonce: peer_nonce,
+                user_agent,
+                last_block,
+                relay,
+                ..
+            } => {
+                if *peer_nonce == nonce {
+                    return Err(Error::SelfConnection);
+                }
+                if peer_info.is_some() {
+                    log::warn!("Ignoring a duplicate version message.");
+                    continue;
+                }
+
+                peer_info = Some(PeerInfo {
+                    addr: stream.peer_addr()?,
+                    version: (*version).min(PROTOCOL_VERSION),
+                    services: *services,
+                    user_agent: String::from_utf8_lossy(user_agent).into_owned(),
+                    start_height: *last_block,
+                    relay: *relay,
+                });
+                stream.write_all(
+                    &Message::from_payload(network_type, Command::Verack, Payload::Verack)
+                        .to_bytes(),
+                )?;
+            }
+            Payload::Verack => verack = true,
+            // Feature negotiation like `sendaddrv2` or `wtxidrelay`, which we do not support.
+            _ => {
+                log::debug!(
+                    "Ignoring {:?} during the handshake.",
+                    message.header().command()
+                );
+            }
+        }
+    }
+
+    stream.set_read_timeout(None)?;
+    let peer_info = peer_info.expect("The loop only ends with the peer´s version.");
+    log::info!(
+        "Handshake with {} ({}, version {}) finished.",
+        peer_info.addr,
+        peer_info.user_agent,
+        peer_info.version
+    );
+
+    Ok(peer_info)
+}
+
+#[cfg(test)]
+mod handshake_test {
+    use std::{io::Write, net::TcpStream, time::Duration};
+
+    use crate::{
+        networking::{
+            Network, NetworkType, PROTOCOL_VERSION, command::Command, error::Error,
+            frame::FrameDecoder, message::Message, payload::Payload,
+        },
+        test_util::{self, send, spawn_peer},
+    };
+
+    /// Run `peer` on the accepted connection of a listener and connect to it.
+    fn connect_to_peer(
+        timeout: Duration,
+        peer: impl FnOnce(TcpStream, FrameDecoder) + Send + 'static,
+    ) -> Result<Network, Error> {
+        Network::connect_with_timeout(spawn_peer(peer), NetworkType::Regtest, timeout)
+    }
+
+    fn peer_version(stream: &TcpStream, nonce: u64) -> Vec<u8> {
+        let mut version = Payload::new_version(
+            stream.local_addr().unwrap(),
+            stream.peer_addr().unwrap(),
+            nonce,
+        );
+        if let Payload::Version {
+            version,
+            services,
+            user_agent,
+            last_block,
+            ..
+        } = &mut version
+        {
+            *version = PROTOCOL_VERSION + 1;
+            *services = 0x0409;
+            *user_agent = b"/Satoshi:27.0.0/".to_vec();
+            *last_block = 850_000;
+        }
+
+        Message::from_payload(NetworkType::Regtest, Command::Version, version).to_bytes()
+    }
+
+    #[test]
+    fn handshake() {
+        let network = connect_to_peer(Duration::from_secs(5), |mut stream, mut decoder| {
+            let version = decoder.read_message(&mut stream).unwrap();
+            assert_eq!(Command::Version, version.header().command());
+
+            // The verack arrives before the version, after a command we do not know.
+            send(
+                &mut stream,
+                Command::SendAddrV2,
+                Payload::Unknown(Vec::new()),
+            );
+            send(&mut stream, Command::Verack, Payload::Verack);
+            // A message after the handshake, likely read together with the version.
+            let mut bytes = peer_version(&stream, 1);
+            bytes.extend_from_slice(
+                &Message::from_payload(
+                    NetworkType::Regtest,
+                    Command::SendHeaders,
+                    Payload::Unknown(Vec::new()),
+                )
+                .to_bytes(),
+            );
+            stream.write_all(&bytes).unwrap();
+
+            let verack = decoder.read_message(&mut stream).unwrap();
+            assert_eq!(Command::Verack, verack.header().command());
+        })
+        .unwrap();
+
+        let peer_info = network.peer_info();
+        assert_eq!(PROTOCOL_VERSION, peer_info.version);
+        assert_eq!(0x0409, peer_info.services);
+        assert_eq!("/Satoshi:27.0.0/", peer_info.user_agent);
+        assert_eq!(850_000, peer_info.start_height);
+        assert!(peer_info.relay);
+
+        let message = test_util::recv(&network);
+        assert_eq!(Command::SendHeaders, message.header().command());
+    }
+
+    #[test]
+    fn self_connection() {
+        let result = connect_to_peer(Duration::from_secs(5), |mut stream, mut decoder| {
+            // Send our own version back.
+            let version = decoder.read_message(&mut stream).unwrap();
+            stream.write_all(&version.to_bytes()).unwrap();
+            let _ = decoder.read_message(&mut stream);
+        });
+
+        assert!(matches!(result, Err(Error::SelfConnection)));
+    }
+
+    #[test]
+    fn timeout() {
+        let result = connect_to_peer(Duration::from_millis(100), |mut stream, mut decoder| {
+            stream.write_all(&peer_version(&stream, 1)).unwrap();
+            // Never send a verack.
+            while decoder.read_message(&mut stream).is_ok() {}
+        });
+
+        assert!(matches!(result, Err(Error::HandshakeTimeout)));
+    }
+}
@@ -1,6 +1,14 @@
+use std::io::{self, Write};
+
 use crate::{
+    encoding::{self, Decodable, Encodable},
     hash::{Hash, Hash256},
-    networking::{NetworkType, command::Command, traits::NetworkInformation},
+    networking::{
+        NetworkType,
+        command::{Command, CommandBytes},
+        error,
+        traits::NetworkInformation,
+    },
 };
 
 #[derive(Debug, Clone, Hash)]
@@ -12,29 +20,38 @@ pub struct Header {
 }
 
 impl Header {
-    /// Takes exactly 24 bytes.
-    pub fn from_bytes(bytes: &[u8]) -> Self {
-        let mut magic_bytes = [0u8; 4];
-        let mut command_bytes = [0u8; 12];
-        let mut size_bytes = [0u8; 4];
-        let mut checksum = [0u8; 4];
-
-        magic_bytes.copy_from_slice(&bytes[..4]);
-        command_bytes.copy_from_slice(&bytes[4..16]);
-        size_bytes.copy_from_slice(&bytes[16..20]);
-        checksum.copy_from_slice(&bytes[20..]);
-
-        let magic_bytes = NetworkType::from_magic_bytes(&magic_bytes);
-        let command = Command::from_bytes(&command_bytes);
+    /// The size of a header in bytes.
+    pub const SIZE: usize = 24;
 
+    /// The header of `payload`, with its size and checksum.
+    pub fn new(network_type: NetworkType, command: Command, payload: &[u8]) -> Self {
         Self {
-            magic_bytes,
+            magic_bytes: network_type,
             command,
-            size: u32::from_le_bytes(size_bytes),
-            checksum,
+            size: payload.len() as u32,
+            checksum: Hash256::digest(payload).checksum(),
         }
     }
 
+    /// Takes exactly 24 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, error::Error> {
+        let mut cursor = bytes;
+        let magic_bytes = <[u8; 4]>::consensus_decode(&mut cursor)?;
+
+        let header = Self {
+            magic_bytes: NetworkType::try_from_magic_bytes(&magic_bytes)
+                .ok_or(error::Error::UnknownMagic(magic_bytes))?,
+            command: Command::from_bytes(&CommandBytes::consensus_decode(&mut cursor)?)?,
+            size: u32::consensus_decode(&mut cursor)?,
+            checksum: <[u8; 4]>::consensus_decode(&mut cursor)?,
+        };
+        if !cursor.is_empty() {
+            return Err(encoding::error::Error::TrailingBytes(cursor.len()).into());
+        }
+
+        Ok(header)
+    }
+
     /// Check the payload.
     pub fn check_payload(&self, payload_hash: &Hash) -> bool {
         payload_hash.check(&self.checksum)
@@ -62,16 +79,23 @@ impl Header {
     }
 
     pub fn to_bytes(self) -> [u8; 24] {
-        let mut bytes = [0u8; 24];
-        bytes[..4].copy_from_slice(&self.magic_bytes.magic_bytes());
-        bytes[4..16].copy_from_slice(&self.command.to_bytes());
-        bytes[16..20].copy_from_slice(&self.size.to_le_bytes());
-        bytes[20..24].copy_from_slice(&self.checksum);
+        let mut bytes = [0u8; Self::SIZE];
+        self.consensus_encode(&mut bytes.as_mut_slice())
+            .expect("A header is exactly 24 bytes.");
 
         bytes
     }
 }
 
+impl Encodable for Header {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        Ok(self.magic_bytes.magic_bytes().consensus_encode(writer)?
+            + self.command.consensus_encode(writer)?
+            + self.size.consensus_encode(writer)?
+            + self.checksum.consensus_encode(writer)?)
+    }
+}
+
 #[cfg(test)]
 mod header_test {
     use crate::networking::{MAGIC_NUMBER_TESTNET3, NetworkType, command::Command, header::Header};
@@ -80,20 +104,26 @@ mod header_test {
     fn from_to_bytes() {
         let checksum = [0xFF, 0xFF, 0xFF, 0xFF];
         let mut bytes = [0u8; 24];
-        bytes[..4].copy_from_slice(&MAGIC_NUMBER_TESTNET3.to_ne_bytes());
+        bytes[..4].copy_from_slice(&MAGIC_NUMBER_TESTNET3.to_be_bytes());
         bytes[4..16].copy_from_slice(&Command::Verack.to_bytes());
         bytes[16..20].copy_from_slice(&69u32.to_le_bytes());
         bytes[20..24].copy_from_slice(&checksum);
 
-        let header = Header::from_bytes(&bytes);
-        
-        assert_eq!(header.network_type(), NetworkType::Testnet);
+        let header = Header::from_bytes(&bytes).unwrap();
+
+        assert_eq!(header.network_type(), NetworkType::Testnet3);
         assert_eq!(header.command(), Command::Verack);
         assert_eq!(header.size(), 69);
         assert_eq!(header.checksum(), checksum);
 
         let bytes_new = header.to_bytes();
-        
+
         assert_eq!(bytes_new, bytes);
+        assert_eq!([0x0B, 0x11, 0x09, 0x07], bytes_new[..4]);
+    }
+
+    #[test]
+    fn short_input() {
+        assert!(Header::from_bytes(&[0x0B, 0x11, 0x09, 0x07, 0x76]).is_err());
     }
 }
@@ -0,0 +1,241 @@
+use std::{
+    collections::VecDeque,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    block::BlockHeader,
+    chain_params::ChainParams,
+    get_unix_timestamp,
+    hash::Hash,
+    header_store::HeaderStore,
+    networking::{
+        Network,
+        command::Command,
+        error::Error,
+        message::Message,
+        payload::{MAX_HEADERS_RESULTS, Payload},
+    },
+};
+
+/// How long to wait for the next message while waiting for a response.
+const RECV_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// # Headers-first sync
+///
+/// ```plaintext
+/// us                              peer
+///  | ---- getheaders(locator) ---> |
+///  | <------ headers(2000) ------- |
+///  | ---- getheaders(locator) ---> |
+///  | <------- headers(17) -------- |
+/// ```
+///
+/// The locator of our best tip lets the peer find the last header we have in common,
+/// it answers with up to [`MAX_HEADERS_RESULTS`] headers after it.
+/// Fewer headers mean we reached the peer´s tip.
+///
+/// See [Headers-First](https://developer.bitcoin.org/devguide/p2p_network.html#headers-first).
+impl Network {
+    /// Download, validate and store the headers of the peer until its tip.
+    ///
+    /// `timeout` applies to each response.
+    /// Returns the count of new headers.
+    pub fn sync_headers(
+        &mut self,
+        store: &mut HeaderStore,
+        params: &ChainParams,
+        timeout: Duration,
+    ) -> Result<usize, Error> {
+        let mut stashed = VecDeque::new();
+        let result = self.sync_headers_stashing(store, params, timeout, &mut stashed);
+
+        // Ahead of the messages received since, in the order they were received.
+        let mut recv_queue = self
+            .recv_queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        while let Some(message) = stashed.pop_back() {
+            recv_queue.push_front(message);
+        }
+
+        result
+    }
+
+    /// [`Self::sync_headers`], the other messages received meanwhile are pushed onto `stashed`.
+    fn sync_headers_stashing(
+        &mut self,
+        store: &mut HeaderStore,
+        params: &ChainParams,
+        timeout: Duration,
+        stashed: &mut VecDeque<Message>,
+    ) -> Result<usize, Error> {
+        let start_len = store.tree().len();
+
+        loop {
+            let len = store.tree().len();
+            self.send_payload(
+                Command::GetHeaders,
+                Payload::new_get_headers(store.tree().locator(), Hash::ZERO),
+            );
+
+            let headers = self.recv_headers(timeout, stashed)?;
+            let count = headers.len();
+            let now = get_unix_timestamp()
+                .expect("Failed to get unix timestamp for header validation.")
+                .as_secs() as u32;
+            store.insert_headers(headers, params, now)?;
+
+            log::info!(
+                "Synced headers to height {} of {}.",
+                store.height(),
+                self.peer_info().start_height
+            );
+
+            // A full batch without new headers would be requested again forever.
+            if count < MAX_HEADERS_RESULTS || store.tree().len() == len {
+                return Ok(store.tree().len() - start_len);
+            }
+        }
+    }
+
+    /// Wait for the next `headers` message, other messages are pushed onto `stashed`.
+    fn recv_headers(
+        &mut self,
+        timeout: Duration,
+        stashed: &mut VecDeque<Message>,
+    ) -> Result<Vec<BlockHeader>, Error> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            match self.recv() {
+                Some(message) => match message.payload() {
+                    Payload::Headers(headers) => return Ok(headers.clone()),
+                    _ => stashed.push_back(message),
+                },
+                None if !self.is_connected() => return Err(Error::Disconnected),
+                None if Instant::now() > deadline => return Err(Error::ResponseTimeout),
+                None => thread::sleep(RECV_POLL_INTERVAL),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod header_sync_test {
+    use std::time::Duration;
+
+    use crate::{
+        block::BlockHeader,
+        chain_params::ChainParams,
+        header_store::HeaderStore,
+        networking::{
+            Network, NetworkType,
+            command::Command,
+            error::Error,
+            inventory::{Inventory, InventoryType},
+            payload::{MAX_HEADERS_RESULTS, Payload},
+        },
+        test_util::{self, TempDir, send, spawn_handshake_peer},
+    };
+
+    /// A regtest chain starting with genesis.
+    fn mine_chain(count: u32) -> Vec<BlockHeader> {
+        let genesis = ChainParams::REGTEST.genesis_block().header().clone();
+        let chain = test_util::mine_chain(&genesis, count, genesis.time + 1);
+
+        [vec![genesis], chain].concat()
+    }
+
+    /// Connect to a peer serving the headers of `chain`, or `bad_headers` once asked.
+    ///
+    /// Every `headers` message follows an `inv` of the tip of `chain`.
+    fn connect_to_peer(chain: Vec<BlockHeader>, bad_headers: Option<Vec<BlockHeader>>) -> Network {
+        let addr = spawn_handshake_peer(move |mut stream, mut decoder| {
+            send(
+                &mut stream,
+                Command::SendHeaders,
+                Payload::Unknown(Vec::new()),
+            );
+
+            while let Ok(message) = decoder.read_message(&mut stream) {
+                let Payload::GetHeaders { locator, .. } = message.payload() else {
+                    continue;
+                };
+
+                let headers = match &bad_headers {
+                    Some(headers) => headers.clone(),
+                    None => {
+                        let start = locator
+                            .iter()
+                            .find_map(|hash| chain.iter().position(|header| header.hash() == *hash))
+                            .unwrap()
+                            + 1;
+                        let end = chain.len().min(start + MAX_HEADERS_RESULTS);
+                        chain[start..end].to_vec()
+                    }
+                };
+                let tip = Inventory::new(InventoryType::Block, chain.last().unwrap().hash());
+                send(&mut stream, Command::Inv, Payload::Inv(vec![tip]));
+                send(&mut stream, Command::Headers, Payload::Headers(headers));
+            }
+        });
+
+        Network::connect(addr, NetworkType::Regtest).unwrap()
+    }
+
+    #[test]
+    fn sync_headers() {
+        let dir = TempDir::new("header_sync_sync");
+        let params = &ChainParams::REGTEST;
+        let chain = mine_chain(MAX_HEADERS_RESULTS as u32 + 5);
+
+        let mut store = HeaderStore::open(dir.path(), params).unwrap();
+        for header in &chain[1..11] {
+            store.insert(header.clone(), params, header.time).unwrap();
+        }
+
+        let mut network = connect_to_peer(chain.clone(), None);
+        let timeout = Duration::from_secs(10);
+        assert_eq!(
+            chain.len() - 11,
+            network.sync_headers(&mut store, params, timeout).unwrap()
+        );
+        assert_eq!(chain.last().unwrap().hash(), store.best_tip().hash);
+
+        // The other messages received during the sync are kept in order.
+        for command in [Command::SendHeaders, Command::Inv] {
+            assert_eq!(command, network.recv().unwrap().header().command());
+        }
+        assert!(network.recv().is_none());
+        assert!(network.has_announced(&chain.last().unwrap().hash()));
+
+        // Already synced.
+        assert_eq!(
+            0,
+            network.sync_headers(&mut store, params, timeout).unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_headers() {
+        let dir = TempDir::new("header_sync_invalid");
+        let params = &ChainParams::REGTEST;
+        let chain = mine_chain(3);
+
+        let mut store = HeaderStore::open(dir.path(), params).unwrap();
+        let mut network = connect_to_peer(chain.clone(), Some(vec![chain[2].clone()]));
+
+        assert!(matches!(
+            network.sync_headers(&mut store, params, Duration::from_secs(10)),
+            Err(Error::HeaderStore(_))
+        ));
+        assert_eq!(0, store.height());
+
+        // Also kept after a failed sync.
+        for command in [Command::SendHeaders, Command::Inv] {
+            assert_eq!(command, network.recv().unwrap().header().command());
+        }
+    }
+}
@@ -0,0 +1,458 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::{self, Read, Write},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    encoding::{self, Decodable, Encodable},
+    hash::Hash,
+};
+
+/// The most inventory vectors in one message.
+pub const MAX_INV_SIZE: usize = 50_000;
+/// How many announced hashes are remembered per peer, older ones are forgotten first.
+pub const MAX_ANNOUNCED: usize = 50_000;
+/// The most requests in flight per peer, the same as Bitcoin Core´s `MAX_PEER_TX_REQUEST_IN_FLIGHT`.
+pub const MAX_IN_FLIGHT: usize = 100;
+/// How long a peer may take to answer a request by default,
+/// the same as Bitcoin Core´s `GETDATA_TX_INTERVAL`.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+const MSG_WITNESS_FLAG: u32 = 1 << 30;
+
+/// # InventoryType
+///
+/// What an [`Inventory`] hash identifies.
+///
+/// See [Inventory Vectors](https://en.bitcoin.it/wiki/Protocol_documentation#Inventory_Vectors).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InventoryType {
+    Error,
+    /// A transaction by txid.
+    Tx,
+    Block,
+    /// A block as a `merkleblock` message (BIP37).
+    FilteredBlock,
+    /// A block as a `cmpctblock` message (BIP152).
+    CmpctBlock,
+    /// A transaction by wtxid (BIP339), only announced after `wtxidrelay`.
+    Wtx,
+    /// [`Self::Tx`] with witness data (BIP144).
+    WitnessTx,
+    /// [`Self::Block`] with witness data (BIP144).
+    WitnessBlock,
+    FilteredWitnessBlock,
+    Unknown(u32),
+}
+
+/// # Inventory
+///
+/// ```plaintext
+/// 02000000 ........................... Type: MSG_BLOCK
+/// 6fe28c0ab6f1b3...0000000000 ........ Hash
+/// ```
+///
+/// > little-endian
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Inventory {
+    pub inv_type: InventoryType,
+    pub hash: Hash,
+}
+
+/// Which inventory a peer announced and which we requested from it.
+///
+/// Announcements are tracked by hash only, a block announced as `MSG_BLOCK`
+/// is also known when requested as `MSG_WITNESS_BLOCK`.
+///
+/// At most [`MAX_IN_FLIGHT`] requests are in flight.
+/// Requests not answered within the timeout expire, so they can be sent to another peer.
+#[derive(Debug)]
+pub(super) struct InventoryTracker {
+    announced: HashSet<Hash>,
+    /// The announced hashes, oldest first.
+    announced_order: VecDeque<Hash>,
+    /// The requested hashes and when they were requested.
+    requested: HashMap<Hash, Instant>,
+    request_timeout: Duration,
+    /// The expired requests, until they are taken.
+    expired: Vec<Hash>,
+}
+
+impl InventoryType {
+    pub const fn from_u32(value: u32) -> Self {
+        match value {
+            0 => Self::Error,
+            1 => Self::Tx,
+            2 => Self::Block,
+            3 => Self::FilteredBlock,
+            4 => Self::CmpctBlock,
+            5 => Self::Wtx,
+            0x4000_0001 => Self::WitnessTx,
+            0x4000_0002 => Self::WitnessBlock,
+            0x4000_0003 => Self::FilteredWitnessBlock,
+            value => Self::Unknown(value),
+        }
+    }
+
+    pub const fn to_u32(self) -> u32 {
+        match self {
+            Self::Error => 0,
+            Self::Tx => 1,
+            Self::Block => 2,
+            Self::FilteredBlock => 3,
+            Self::CmpctBlock => 4,
+            Self::Wtx => 5,
+            Self::WitnessTx => MSG_WITNESS_FLAG | 1,
+            Self::WitnessBlock => MSG_WITNESS_FLAG | 2,
+            Self::FilteredWitnessBlock => MSG_WITNESS_FLAG | 3,
+            Self::Unknown(value) => value,
+        }
+    }
+
+    pub const fn is_block(&self) -> bool {
+        matches!(
+            self,
+            Self::Block
+                | Self::FilteredBlock
+                | Self::CmpctBlock
+                | Self::WitnessBlock
+                | Self::FilteredWitnessBlock
+        )
+    }
+
+    pub const fn is_tx(&self) -> bool {
+        matches!(self, Self::Tx | Self::Wtx | Self::WitnessTx)
+    }
+}
+
+impl Inventory {
+    pub const fn new(inv_type: InventoryType, hash: Hash) -> Self {
+        Self { inv_type, hash }
+    }
+}
+
+impl InventoryTracker {
+    pub(super) fn new(request_timeout: Duration) -> Self {
+        Self {
+            announced: HashSet::new(),
+            announced_order: VecDeque::new(),
+            requested: HashMap::new(),
+            request_timeout,
+            expired: Vec::new(),
+        }
+    }
+
+    /// Remember the hashes of `inventory` as announced.
+    pub(super) fn announce(&mut self, inventory: &[Inventory]) {
+        for inv in inventory {
+            if !self.announced.insert(inv.hash) {
+                continue;
+            }
+
+            self.announced_order.push_back(inv.hash);
+            if self.announced_order.len() > MAX_ANNOUNCED {
+                let oldest = self
+                    .announced_order
+                    .pop_front()
+                    .expect("The queue is not empty.");
+                self.announced.remove(&oldest);
+            }
+        }
+    }
+
+    pub(super) fn has_announced(&self, hash: &Hash) -> bool {
+        self.announced.contains(hash)
+    }
+
+    /// Remember `inventory` as requested at `now`, up to [`MAX_IN_FLIGHT`] requests.
+    ///
+    /// Returns the inventory to send, without the requests already in flight,
+    /// and the inventory over the limit.
+    pub(super) fn request(
+        &mut self,
+        inventory: Vec<Inventory>,
+        now: Instant,
+    ) -> (Vec<Inventory>, Vec<Inventory>) {
+        let mut send = Vec::new();
+        let mut over_limit = Vec::new();
+
+        for inv in inventory {
+            if self.requested.contains_key(&inv.hash) {
+                continue;
+            }
+            if self.requested.len() >= MAX_IN_FLIGHT {
+                over_limit.push(inv);
+                continue;
+            }
+
+            self.requested.insert(inv.hash, now);
+            send.push(inv);
+        }
+
+        (send, over_limit)
+    }
+
+    /// Expire the requests older than the timeout.
+    pub(super) fn expire(&mut self, now: Instant) {
+        let request_timeout = self.request_timeout;
+        let expired = &mut self.expired;
+
+        self.requested.retain(|hash, requested_at| {
+            let is_stale = now.saturating_duration_since(*requested_at) > request_timeout;
+            if is_stale {
+                log::debug!("The request of {hash} expired.");
+                expired.push(*hash);
+            }
+
+            !is_stale
+        });
+
+        // Only if they are never taken.
+        if self.expired.len() > MAX_ANNOUNCED {
+            self.expired.drain(..self.expired.len() - MAX_ANNOUNCED);
+        }
+    }
+
+    /// When the oldest request in flight expires.
+    pub(super) fn next_expiry(&self) -> Option<Instant> {
+        self.requested
+            .values()
+            .min()
+            .map(|requested_at| *requested_at + self.request_timeout)
+    }
+
+    /// The expired requests since the last call, at most [`MAX_ANNOUNCED`].
+    pub(super) fn take_expired(&mut self) -> Vec<Hash> {
+        std::mem::take(&mut self.expired)
+    }
+
+    /// When `hash` was requested, if it was not received, not found or expired yet.
+    pub(super) fn requested_at(&self, hash: &Hash) -> Option<Instant> {
+        self.requested.get(hash).copied()
+    }
+
+    /// A requested object was received or is not found.
+    pub(super) fn complete(&mut self, hash: &Hash) {
+        self.requested.remove(hash);
+    }
+}
+
+impl Encodable for Inventory {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        Ok(
+            self.inv_type.to_u32().consensus_encode(writer)?
+                + self.hash.consensus_encode(writer)?,
+        )
+    }
+}
+
+impl Decodable for Inventory {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, encoding::error::Error> {
+        Ok(Self {
+            inv_type: InventoryType::from_u32(u32::consensus_decode(reader)?),
+            hash: Hash::consensus_decode(reader)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod inventory_test {
+    use std::{
+        sync::mpsc,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        chain_params::ChainParams,
+        encoding::{Decodable, Encodable},
+        hash::Hash,
+        networking::{
+            ConnectOptions, Network, NetworkType,
+            command::Command,
+            inventory::{Inventory, InventoryTracker, InventoryType, MAX_ANNOUNCED, MAX_IN_FLIGHT},
+            payload::Payload,
+        },
+        test_util::{recv, send, spawn_handshake_peer, wait_until},
+    };
+
+    /// The mainnet genesis block as `MSG_WITNESS_BLOCK`.
+    const GENESIS_INVENTORY: &str =
+        "020000406fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000";
+
+    #[test]
+    fn from_to_bytes() {
+        let bytes = hex::decode(GENESIS_INVENTORY).unwrap();
+        let inventory = Inventory::from_consensus_bytes(&bytes).unwrap();
+        assert_eq!(InventoryType::WitnessBlock, inventory.inv_type);
+        assert_eq!(
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+            inventory.hash.to_string()
+        );
+        assert_eq!(bytes, inventory.to_consensus_bytes());
+
+        for value in [0, 1, 2, 3, 4, 5, 0x4000_0001, 0x4000_0002, 0x4000_0003, 6] {
+            assert_eq!(value, InventoryType::from_u32(value).to_u32());
+        }
+        assert_eq!(InventoryType::Unknown(6), InventoryType::from_u32(6));
+        assert!(InventoryType::CmpctBlock.is_block());
+        assert!(InventoryType::Wtx.is_tx());
+        assert!(!InventoryType::Error.is_tx());
+    }
+
+    #[test]
+    fn tracker() {
+        let hash = |i: u32| {
+            let mut bytes = [0u8; 32];
+            bytes[..4].copy_from_slice(&i.to_le_bytes());
+            Hash::from_natural_bytes(bytes)
+        };
+        let mut tracker = InventoryTracker::new(Duration::from_secs(60));
+
+        let inventory = (0..=MAX_ANNOUNCED as u32)
+            .map(|i| Inventory::new(InventoryType::Tx, hash(i)))
+            .collect::<Vec<_>>();
+        tracker.announce(&inventory[..2]);
+        tracker.announce(&inventory[1..]);
+        // The oldest one is forgotten.
+        assert!(!tracker.has_announced(&hash(0)));
+        assert!(tracker.has_announced(&hash(1)));
+        assert!(tracker.has_announced(&hash(MAX_ANNOUNCED as u32)));
+
+        let now = Instant::now();
+        let (send, over_limit) = tracker.request(inventory[..1].to_vec(), now);
+        assert_eq!(inventory[..1], send);
+        assert!(over_limit.is_empty());
+        assert_eq!(Some(now), tracker.requested_at(&hash(0)));
+        tracker.complete(&hash(0));
+        assert_eq!(None, tracker.requested_at(&hash(0)));
+    }
+
+    #[test]
+    fn tracker_limits() {
+        let hash = |i: u32| {
+            let mut bytes = [0u8; 32];
+            bytes[..4].copy_from_slice(&i.to_le_bytes());
+            Hash::from_natural_bytes(bytes)
+        };
+        let second = Duration::from_secs(1);
+        let mut tracker = InventoryTracker::new(60 * second);
+        let inventory = (0..MAX_IN_FLIGHT as u32 + 10)
+            .map(|i| Inventory::new(InventoryType::Tx, hash(i)))
+            .collect::<Vec<_>>();
+
+        let start = Instant::now();
+        let (send, over_limit) = tracker.request(inventory[..MAX_IN_FLIGHT - 1].to_vec(), start);
+        assert_eq!(MAX_IN_FLIGHT - 1, send.len());
+        assert!(over_limit.is_empty());
+
+        // Requests in flight are not sent again, the rest is over the limit.
+        let (send, over_limit) = tracker.request(inventory[1..].to_vec(), start + 10 * second);
+        assert_eq!(inventory[MAX_IN_FLIGHT - 1..MAX_IN_FLIGHT], send);
+        assert_eq!(inventory[MAX_IN_FLIGHT..], over_limit);
+        assert_eq!(Some(start + 60 * second), tracker.next_expiry());
+
+        tracker.expire(start + 60 * second);
+        assert!(tracker.take_expired().is_empty());
+        tracker.expire(start + 61 * second);
+        let expired = tracker.take_expired();
+        assert_eq!(MAX_IN_FLIGHT - 1, expired.len());
+        assert!(!expired.contains(&hash(MAX_IN_FLIGHT as u32 - 1)));
+        assert_eq!(None, tracker.requested_at(&hash(0)));
+        assert!(tracker.take_expired().is_empty());
+        assert_eq!(Some(start + 70 * second), tracker.next_expiry());
+
+        // Room for new requests.
+        let (send, over_limit) = tracker.request(inventory[MAX_IN_FLIGHT..].to_vec(), start);
+        assert_eq!(10, send.len());
+        assert!(over_limit.is_empty());
+    }
+
+    #[test]
+    fn request() {
+        let genesis = ChainParams::REGTEST.genesis_block();
+        let block_hash = genesis.block_header_hash256();
+        let missing_hash = Hash::from_natural_bytes([0x42; 32]);
+
+        let peer_genesis = genesis.clone();
+        let (getdata_sender, getdata_receiver) = mpsc::channel();
+        let addr = spawn_handshake_peer(move |mut stream, mut decoder| {
+            send(
+                &mut stream,
+                Command::Inv,
+                Payload::Inv(vec![Inventory::new(InventoryType::Block, block_hash)]),
+            );
+
+            while let Ok(message) = decoder.read_message(&mut stream) {
+                let Payload::GetData(inventory) = message.payload() else {
+                    continue;
+                };
+                getdata_sender.send(inventory.clone()).unwrap();
+
+                send(
+                    &mut stream,
+                    Command::Block,
+                    Payload::Block(peer_genesis.clone()),
+                );
+                send(
+                    &mut stream,
+                    Command::NotFound,
+                    Payload::NotFound(inventory[1..].to_vec()),
+                );
+            }
+        });
+
+        let mut network = Network::connect(addr, NetworkType::Regtest).unwrap();
+        assert!(matches!(recv(&network).payload(), Payload::Inv(_)));
+        assert!(network.has_announced(&block_hash));
+        assert!(!network.has_announced(&missing_hash));
+
+        let inventory = vec![
+            Inventory::new(InventoryType::WitnessBlock, block_hash),
+            Inventory::new(InventoryType::WitnessTx, missing_hash),
+        ];
+        assert!(network.request(inventory.clone()).is_empty());
+        assert_eq!(
+            inventory,
+            getdata_receiver
+                .recv_timeout(Duration::from_secs(5))
+                .unwrap()
+        );
+
+        let message = recv(&network);
+        assert!(matches!(message.payload(), Payload::Block(block) if *block == genesis));
+        assert!(network.requested_at(&block_hash).is_none());
+
+        let message = recv(&network);
+        assert!(matches!(
+            message.payload(),
+            Payload::NotFound(inventory) if inventory[0].hash == missing_hash
+        ));
+        assert!(network.requested_at(&missing_hash).is_none());
+    }
+
+    #[test]
+    fn request_timeout() {
+        let hash = Hash::from_natural_bytes([0x42; 32]);
+        let addr = spawn_handshake_peer(|mut stream, mut decoder| {
+            // Never answer.
+            while decoder.read_message(&mut stream).is_ok() {}
+        });
+        let options = ConnectOptions {
+            request_timeout: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let mut network =
+            Network::connect_with_options(addr, NetworkType::Regtest, options).unwrap();
+
+        network.request(vec![Inventory::new(InventoryType::Tx, hash)]);
+        let mut expired = Vec::new();
+        wait_until(|| {
+            expired.extend(network.take_expired_requests());
+            !expired.is_empty()
+        });
+        assert_eq!(vec![hash], expired);
+        assert_eq!(None, network.requested_at(&hash));
+        assert!(network.is_connected());
+    }
+}
@@ -1,4 +1,11 @@
-use crate::networking::{header::Header, payload::Payload, traits::FromToIpV6};
+use std::io::{self, Write};
+
+use crate::{
+    encoding::Encodable,
+    networking::{
+        NetworkType, command::Command, header::Header, payload::Payload, traits::FromToIpV6,
+    },
+};
 
 #[derive(Debug, Clone, Hash)]
 pub struct Message {
@@ -11,6 +18,13 @@ impl Message {
         Self { header, payload }
     }
 
+    /// A message with the header computed from `payload`.
+    pub fn from_payload(network_type: NetworkType, command: Command, payload: Payload) -> Self {
+        let header = Header::new(network_type, command, &payload.to_consensus_bytes());
+
+        Self::new(header, payload)
+    }
+
     pub fn header(&self) -> &Header {
         &self.header
     }
@@ -19,16 +33,17 @@ impl Message {
         &self.payload
     }
 
+    /// Convert into bytes.
+    ///
+    /// The payload is encoded as it is, as the checksum of the header commits to it.
     pub fn to_bytes(self) -> Vec<u8> {
-        let mut payload = self.payload.to_bytes();
-        payload.reserve_exact(24);
-        let len = payload.len();
-        // Make space for header.
-        payload.copy_within(..len, 24);
-        payload[..24].copy_from_slice(&self.header.to_bytes());
-        payload.shrink_to_fit();
-
-        payload
+        self.to_consensus_bytes()
+    }
+}
+
+impl Encodable for Message {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        Ok(self.header.consensus_encode(writer)? + self.payload.consensus_encode(writer)?)
     }
 }
 
@@ -1,11 +1,30 @@
-use std::net;
+use std::{
+    io::{self, Read, Write},
+    net,
+};
 
 use crate::{
+    block::{Block, BlockHeader, Transaction},
+    encoding::{self, CompactSize, Decodable, Encodable},
     get_unix_timestamp,
-    hash::Hash256,
-    networking::{PROTOCOL_VERSION, command::Command, error, header::Header, traits::FromToIpV6},
+    hash::{Hash, Hash256},
+    networking::{
+        PROTOCOL_VERSION, USER_AGENT,
+        command::Command,
+        error,
+        header::Header,
+        inventory::{Inventory, MAX_INV_SIZE},
+        traits::FromToIpV6,
+    },
 };
 
+/// The longest user agent Bitcoin Core accepts.
+pub const MAX_USER_AGENT_LENGTH: usize = 256;
+/// The most headers in a `headers` message.
+pub const MAX_HEADERS_RESULTS: usize = 2000;
+/// The most hashes in a block locator Bitcoin Core accepts.
+pub const MAX_LOCATOR_SIZE: usize = 101;
+
 #[derive(Debug, Clone, Hash)]
 pub enum Payload {
     Version {
@@ -28,14 +47,64 @@ pub enum Payload {
         user_agent: Vec<u8>,
         ///The latest block of our blockchain.
         last_block: u32,
+        /// Whether the peer wants to receive transactions before sending a filter (BIP37).
+        relay: bool,
     },
     Verack,
-    ChecksumMismatch(Vec<u8>),
+    /// The nonce the [`Self::Pong`] has to answer with.
+    Ping(u64),
+    /// The nonce of the answered [`Self::Ping`].
+    Pong(u64),
+    /// Request the headers after the first hash of `locator` the peer knows,
+    /// up to [`MAX_HEADERS_RESULTS`] or `stop_hash`.
+    GetHeaders {
+        version: u32,
+        /// See [`HeaderTree::locator`](crate::block::HeaderTree::locator).
+        locator: Vec<Hash>,
+        /// Zero for as many headers as possible.
+        stop_hash: Hash,
+    },
+    /// Each header is followed by a transaction count, which is always zero.
+    Headers(Vec<BlockHeader>),
+    /// Announce blocks or transactions.
+    Inv(Vec<Inventory>),
+    /// Request announced blocks or transactions.
+    GetData(Vec<Inventory>),
+    /// The requested inventory the peer does not have.
+    NotFound(Vec<Inventory>),
+    Block(Block),
+    Tx(Transaction),
+    /// The raw payload of a command without its own variant.
+    Unknown(Vec<u8>),
 }
 
 impl Payload {
-    pub fn new_version(remote_addr: net::SocketAddr, local_addr: net::SocketAddr) -> Self {
-        Self::Version {
+    /// A version with our [`USER_AGENT`].
+    pub fn new_version(
+        remote_addr: net::SocketAddr,
+        local_addr: net::SocketAddr,
+        nonce: u64,
+    ) -> Self {
+        Self::new_version_with_user_agent(remote_addr, local_addr, nonce, USER_AGENT)
+            .expect("Our user agent is valid.")
+    }
+
+    /// A version with `user_agent`, which has to be ASCII
+    /// and at most [`MAX_USER_AGENT_LENGTH`] bytes.
+    pub fn new_version_with_user_agent(
+        remote_addr: net::SocketAddr,
+        local_addr: net::SocketAddr,
+        nonce: u64,
+        user_agent: &str,
+    ) -> Result<Self, error::Error> {
+        if !user_agent.is_ascii() {
+            return Err(error::Error::NonAsciiUserAgent);
+        }
+        if user_agent.len() > MAX_USER_AGENT_LENGTH {
+            return Err(error::Error::UserAgentTooLong(user_agent.len() as u64));
+        }
+
+        Ok(Self::Version {
             version: PROTOCOL_VERSION,
             services: 0,
             time: get_unix_timestamp()
@@ -45,18 +114,199 @@ impl Payload {
             remote_addr,
             local_services: 0,
             local_addr,
-            nonce: 0,
-            user_agent: Vec::new(),
+            nonce,
+            user_agent: user_agent.as_bytes().to_vec(),
             last_block: 0,
+            relay: true,
+        })
+    }
+
+    pub fn new_get_headers(locator: Vec<Hash>, stop_hash: Hash) -> Self {
+        Self::GetHeaders {
+            version: PROTOCOL_VERSION,
+            locator,
+            stop_hash,
+        }
+    }
+
+    /// Convert into bytes.
+    ///
+    /// The `time` of [`Self::Version`] is updated to the current time.
+    pub fn to_bytes(mut self) -> Vec<u8> {
+        if let Self::Version { time, .. } = &mut self {
+            *time = get_unix_timestamp()
+                .expect("Invalid unix timestamp while converting a version payload to bytes.")
+                .as_secs();
+        }
+
+        self.to_consensus_bytes()
+    }
+
+    /// Decode the payload of `header`, if its checksum matches.
+    pub fn from_bytes(header: &Header, bytes: &[u8]) -> Result<Self, error::Error> {
+        if !header.check_payload(&Hash256::digest(bytes)) {
+            return Err(error::Error::ChecksumMismatch);
+        };
+
+        let payload = match header.command() {
+            Command::Version => Self::decode_version(bytes)?,
+            Command::Verack => Self::Verack,
+            Command::Ping => Self::Ping(u64::from_consensus_bytes(bytes)?),
+            Command::Pong => Self::Pong(u64::from_consensus_bytes(bytes)?),
+            Command::GetHeaders => Self::decode_get_headers(bytes)?,
+            Command::Headers => Self::decode_headers(bytes)?,
+            Command::Inv => Self::Inv(Self::decode_inventory(bytes)?),
+            Command::GetData => Self::GetData(Self::decode_inventory(bytes)?),
+            Command::NotFound => Self::NotFound(Self::decode_inventory(bytes)?),
+            Command::Block => Self::Block(Block::from_bytes(bytes)?),
+            Command::Tx => Self::Tx(Transaction::from_bytes(bytes)?),
+            _ => Self::Unknown(bytes.to_vec()),
+        };
+
+        Ok(payload)
+    }
+
+    fn decode_get_headers(bytes: &[u8]) -> Result<Self, error::Error> {
+        let mut cursor = bytes;
+        let version = u32::consensus_decode(&mut cursor)?;
+        let locator = Vec::<Hash>::consensus_decode(&mut cursor)?;
+        if locator.len() > MAX_LOCATOR_SIZE {
+            return Err(error::Error::LocatorTooLong(locator.len()));
+        }
+
+        Ok(Self::GetHeaders {
+            version,
+            locator,
+            stop_hash: Hash::from_consensus_bytes(cursor)?,
+        })
+    }
+
+    fn decode_headers(bytes: &[u8]) -> Result<Self, error::Error> {
+        let mut cursor = bytes;
+        let count = CompactSize::consensus_decode(&mut cursor)?.0;
+        if count > MAX_HEADERS_RESULTS as u64 {
+            return Err(error::Error::TooManyHeaders(count));
+        }
+
+        let mut headers = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            headers.push(BlockHeader::consensus_decode(&mut cursor)?);
+            // The transaction count, ignored the same as by Bitcoin Core.
+            CompactSize::consensus_decode(&mut cursor)?;
+        }
+        if !cursor.is_empty() {
+            return Err(encoding::error::Error::TrailingBytes(cursor.len()).into());
         }
+
+        Ok(Self::Headers(headers))
+    }
+
+    fn decode_inventory(bytes: &[u8]) -> Result<Vec<Inventory>, error::Error> {
+        let mut cursor = bytes;
+        let count = CompactSize::consensus_decode(&mut cursor)?.0;
+        if count > MAX_INV_SIZE as u64 {
+            return Err(error::Error::TooManyInventory(count));
+        }
+
+        let mut inventory = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            inventory.push(Inventory::consensus_decode(&mut cursor)?);
+        }
+        if !cursor.is_empty() {
+            return Err(encoding::error::Error::TrailingBytes(cursor.len()).into());
+        }
+
+        Ok(inventory)
+    }
+
+    /// Decode a `version` payload.
+    ///
+    /// ```plaintext
+    /// 62ea0000 ........................... Version: 60002
+    /// 0100000000000000 ................... Services
+    /// 11b2d05000000000 ................... Time
+    /// 0100000000000000 ................... Remote services
+    /// 00000000000000000000ffff00000000 ... Remote address
+    /// 0000 ............................... Remote port
+    /// 0100000000000000 ................... Local services
+    /// 00000000000000000000ffff00000000 ... Local address
+    /// 0000 ............................... Local port
+    /// 3b2eb35d8ce61765 ................... Nonce
+    /// 0f ................................. User agent size
+    /// 2f5361746f7368693a302e372e322f ..... User agent: /Satoshi:0.7.2/
+    /// c03e0300 ........................... Last block: 212672
+    /// 01 ................................. Relay (since version 70001)
+    /// ```
+    ///
+    /// Old peers send shorter messages.
+    /// The same as Bitcoin Core, every field after the remote address is optional
+    /// and defaults to zero, empty or `relay = true`.
+    ///
+    /// See [version](https://en.bitcoin.it/wiki/Protocol_documentation#version).
+    fn decode_version(bytes: &[u8]) -> Result<Self, error::Error> {
+        let mut cursor = bytes;
+
+        let version = u32::consensus_decode(&mut cursor)?;
+        let services = u64::consensus_decode(&mut cursor)?;
+        let time = u64::consensus_decode(&mut cursor)?;
+        let remote_services = u64::consensus_decode(&mut cursor)?;
+        let remote_addr = net::SocketAddr::consensus_decode(&mut cursor)?;
+
+        let (local_services, local_addr, nonce) = if cursor.is_empty() {
+            (0, net::SocketAddr::from(([0u8; 16], 0)), 0)
+        } else {
+            (
+                u64::consensus_decode(&mut cursor)?,
+                net::SocketAddr::consensus_decode(&mut cursor)?,
+                u64::consensus_decode(&mut cursor)?,
+            )
+        };
+
+        let user_agent = if cursor.is_empty() {
+            Vec::new()
+        } else {
+            let size = CompactSize::consensus_decode(&mut cursor)?.0;
+            if size > MAX_USER_AGENT_LENGTH as u64 {
+                return Err(error::Error::UserAgentTooLong(size));
+            }
+
+            let mut user_agent = vec![0u8; size as usize];
+            encoding::read_exact(&mut cursor, &mut user_agent)?;
+            user_agent
+        };
+
+        let last_block = if cursor.is_empty() {
+            0
+        } else {
+            u32::consensus_decode(&mut cursor)?
+        };
+
+        // Anything but zero is true, trailing bytes are ignored.
+        let relay = cursor.first().is_none_or(|relay| *relay != 0);
+
+        Ok(Self::Version {
+            version,
+            services,
+            time,
+            remote_services,
+            remote_addr,
+            local_services,
+            local_addr,
+            nonce,
+            user_agent,
+            last_block,
+            relay,
+        })
     }
+}
 
-    pub fn to_bytes(self) -> Vec<u8> {
+impl Encodable for Payload {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
         match self {
             Self::Version {
                 version,
                 services,
-                time: _,
+                time,
                 remote_services,
                 remote_addr,
                 local_services,
@@ -64,115 +314,277 @@ impl Payload {
                 nonce,
                 user_agent,
                 last_block,
-            } => {
-                /// Size in bytes of version. Excludes `user_agent` because of dynamic size.
-                const SIZE_OF_VERSION: usize = 84;
-
-                if !user_agent.is_ascii() {
-                    let err_msg = format!(
-                        "Failed to convert `BtcMessage::Version` into `BtcMessageBytes`. user_agent not a valid ASCII String!"
-                    );
-                    log::error!("{}", err_msg);
-                    panic!("{}", err_msg);
-                };
-
-                let user_agent_bytes = if user_agent.is_empty() {
-                    vec![0]
-                } else {
-                    user_agent
-                };
-
-                let mut bytes = Vec::with_capacity(SIZE_OF_VERSION + user_agent_bytes.len());
-
-                bytes[..4].copy_from_slice(&version.to_ne_bytes());
-                bytes[4..12].copy_from_slice(&services.to_ne_bytes());
-                bytes[12..20].copy_from_slice(
-                    &get_unix_timestamp()
-                        .expect("Invalid unix timestamp while converting a BtcMessage to bytes.")
-                        .as_secs()
-                        .to_le_bytes(),
-                );
-                bytes[20..28].copy_from_slice(&remote_services.to_ne_bytes());
-                bytes[28..44].copy_from_slice(&remote_addr.ip().to_v6().to_bits().to_be_bytes());
-                bytes[44..46].copy_from_slice(&remote_addr.port().to_be_bytes());
-                bytes[46..54].copy_from_slice(&local_services.to_ne_bytes());
-                bytes[54..70].copy_from_slice(&local_addr.ip().to_v6().to_bits().to_be_bytes());
-                bytes[70..72].copy_from_slice(&local_addr.port().to_be_bytes());
-                bytes[72..80].copy_from_slice(&nonce.to_ne_bytes());
-                let usr_agnt_end_idx = 80 + user_agent_bytes.len();
-                bytes[80..usr_agnt_end_idx].copy_from_slice(&user_agent_bytes);
-                bytes[usr_agnt_end_idx..usr_agnt_end_idx + 4]
-                    .copy_from_slice(&last_block.to_ne_bytes());
-
-                bytes
+                relay,
+            } => Ok(version.consensus_encode(writer)?
+                + services.consensus_encode(writer)?
+                + time.consensus_encode(writer)?
+                + remote_services.consensus_encode(writer)?
+                + remote_addr.consensus_encode(writer)?
+                + local_services.consensus_encode(writer)?
+                + local_addr.consensus_encode(writer)?
+                + nonce.consensus_encode(writer)?
+                + user_agent.consensus_encode(writer)?
+                + last_block.consensus_encode(writer)?
+                + relay.consensus_encode(writer)?),
+            Self::Verack => Ok(0),
+            Self::Ping(nonce) | Self::Pong(nonce) => nonce.consensus_encode(writer),
+            Self::GetHeaders {
+                version,
+                locator,
+                stop_hash,
+            } => Ok(version.consensus_encode(writer)?
+                + locator.consensus_encode(writer)?
+                + stop_hash.consensus_encode(writer)?),
+            Self::Headers(headers) => {
+                let mut len = CompactSize(headers.len() as u64).consensus_encode(writer)?;
+                for header in headers {
+                    len += header.consensus_encode(writer)?
+                        + CompactSize(0).consensus_encode(writer)?;
+                }
+
+                Ok(len)
             }
-            Self::Verack => {
-                todo!()
+            Self::Inv(inventory) | Self::GetData(inventory) | Self::NotFound(inventory) => {
+                inventory.consensus_encode(writer)
             }
-            Self::ChecksumMismatch(payload) => {
-                log::warn!("To bytes on checksum mismatched payload!");
+            Self::Block(block) => block.consensus_encode(writer),
+            Self::Tx(transaction) => transaction.consensus_encode(writer),
+            Self::Unknown(payload) => {
+                writer.write_all(payload)?;
 
-                payload
+                Ok(payload.len())
             }
         }
     }
+}
 
-    pub fn from_bytes(header: &Header, bytes: &[u8]) -> Result<Self, error::Error> {
-        if header.check_payload(&Hash256::digest(bytes)) {
-            return Err(error::Error::ChecksumMismatch);
+/// A network address without time and services: a 16 byte IPv6 address and a big-endian port.
+///
+/// IPv4 addresses are mapped into IPv6.
+impl Encodable for net::SocketAddr {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        writer.write_all(&self.ip().to_v6().to_bits().to_be_bytes())?;
+        writer.write_all(&self.port().to_be_bytes())?;
+
+        Ok(18)
+    }
+}
+
+impl Decodable for net::SocketAddr {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, encoding::error::Error> {
+        let ip = <[u8; 16]>::consensus_decode(reader)?;
+        let port = <[u8; 2]>::consensus_decode(reader)?;
+
+        Ok(net::SocketAddr::new(
+            net::IpAddr::from_be_bytes(ip),
+            u16::from_be_bytes(port),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod payload_test {
+    use std::net;
+
+    use crate::{
+        encoding::Encodable,
+        networking::{
+            NetworkType, USER_AGENT, command::Command, error::Error, header::Header,
+            message::Message, payload::Payload,
+        },
+    };
+
+    /// A `version` message of Bitcoin Core 0.7.2, before the relay flag.
+    const VERSION_60002: &str = "f9beb4d976657273696f6e0000000000640000003b648d5a\
+        62ea0000010000000000000011b2d05000000000010000000000000000000000000000000000ffff00000000\
+        0000010000000000000000000000000000000000ffff0000000000003b2eb35d8ce617650f2f5361746f73\
+        68693a302e372e322fc03e0300";
+
+    /// A `version` message as Bitcoin Core 27.0 sends it to an inbound peer:
+    /// protocol 70016, the relay flag set and the unspecified address as its own.
+    const VERSION_70016: &str = "f9beb4d976657273696f6e000000000066000000dbe170e2\
+        80110100090c0000000000008062206600000000\
+        000000000000000000000000000000000000ffffcb007107c822\
+        090c000000000000000000000000000000000000000000000000\
+        26f9d4071c5b8e3a102f5361746f7368693a32372e302e302f\
+        40d10c0001";
+
+    fn decode(bytes: &[u8]) -> Result<Payload, Error> {
+        let header = Header::from_bytes(&bytes[..Header::SIZE])?;
+        Payload::from_bytes(&header, &bytes[Header::SIZE..])
+    }
+
+    #[test]
+    fn version_60002() {
+        let bytes = hex::decode(VERSION_60002).unwrap();
+        let payload = decode(&bytes).unwrap();
+
+        let Payload::Version {
+            version,
+            services,
+            time,
+            remote_services,
+            remote_addr,
+            local_addr,
+            nonce,
+            user_agent,
+            last_block,
+            relay,
+            ..
+        } = &payload
+        else {
+            panic!("Not a version payload: {payload:?}");
         };
+        assert_eq!(60002, *version);
+        assert_eq!(1, *services);
+        assert_eq!(1_355_854_353, *time);
+        assert_eq!(1, *remote_services);
+        assert_eq!(
+            net::SocketAddr::from(([0, 0, 0, 0, 0, 0xFFFF, 0, 0], 0)),
+            *remote_addr
+        );
+        assert_eq!(*remote_addr, *local_addr);
+        assert_eq!(0x6517_E68C_5DB3_2E3B, *nonce);
+        assert_eq!(b"/Satoshi:0.7.2/", user_agent.as_slice());
+        assert_eq!(212_672, *last_block);
+        assert!(*relay);
 
-        let payload = match header.command() {
-            Command::Version => {
-                let mut version_bytes = [0u8; 4];
-                let mut services_bytes = [0u8; 8];
-                let mut time_bytes = [0u8; 8];
-                let mut remote_services_bytes = [0u8; 8];
-                let mut remote_ip_bytes = [0u8; 16];
-                let mut remote_port_bytes = [0u8; 2];
-                let mut local_services_bytes = [0u8; 8];
-                let mut local_ip_bytes = [0u8; 16];
-                let mut local_port_bytes = [0u8; 2];
-                let mut nonce_bytes = [0u8; 8];
-                let mut user_agent_bytes = Vec::with_capacity(1024);
-                let mut last_block_bytes = [0u8; 4];
-
-                version_bytes.copy_from_slice(&bytes[..4]);
-                services_bytes.copy_from_slice(&bytes[4..12]);
-                time_bytes.copy_from_slice(&bytes[12..20]);
-                remote_services_bytes.copy_from_slice(&bytes[20..28]);
-                remote_ip_bytes.copy_from_slice(&bytes[28..44]);
-                remote_port_bytes.copy_from_slice(&bytes[44..46]);
-                local_services_bytes.copy_from_slice(&bytes[46..54]);
-                local_ip_bytes.copy_from_slice(&bytes[54..70]);
-                local_port_bytes.copy_from_slice(&bytes[70..72]);
-                nonce_bytes.copy_from_slice(&bytes[72..80]);
-                let usr_agnt_end = bytes.len() - 4;
-                user_agent_bytes.copy_from_slice(&bytes[80..usr_agnt_end]);
-                last_block_bytes.copy_from_slice(&bytes[usr_agnt_end..usr_agnt_end + 4]);
-
-                Self::Version {
-                    version: u32::from_ne_bytes(version_bytes),
-                    services: u64::from_ne_bytes(services_bytes),
-                    time: u64::from_le_bytes(time_bytes),
-                    remote_services: u64::from_ne_bytes(remote_services_bytes),
-                    remote_addr: net::SocketAddr::new(
-                        net::IpAddr::from_be_bytes(remote_ip_bytes),
-                        u16::from_be_bytes(remote_port_bytes),
-                    ),
-                    local_services: u64::from_ne_bytes(local_services_bytes),
-                    local_addr: net::SocketAddr::new(
-                        net::IpAddr::from_be_bytes(local_ip_bytes),
-                        u16::from_be_bytes(local_port_bytes),
-                    ),
-                    nonce: u64::from_ne_bytes(nonce_bytes),
-                    user_agent: user_agent_bytes,
-                    last_block: u32::from_ne_bytes(last_block_bytes),
-                }
+        // Re-encoded with the relay flag.
+        let encoded = payload.to_consensus_bytes();
+        assert_eq!(bytes[Header::SIZE..], encoded[..100]);
+        assert_eq!([0x01], encoded[100..]);
+    }
+
+    #[test]
+    fn version_70016() {
+        let bytes = hex::decode(VERSION_70016).unwrap();
+        let payload = decode(&bytes).unwrap();
+
+        let Payload::Version {
+            version,
+            services,
+            time,
+            remote_services,
+            remote_addr,
+            local_services,
+            local_addr,
+            nonce,
+            user_agent,
+            last_block,
+            relay,
+        } = &payload
+        else {
+            panic!("Not a version payload: {payload:?}");
+        };
+        assert_eq!(70016, *version);
+        // NODE_NETWORK, NODE_WITNESS, NODE_NETWORK_LIMITED and NODE_P2P_V2.
+        assert_eq!(0x0C09, *services);
+        assert_eq!(1_713_398_400, *time);
+        assert_eq!(0, *remote_services);
+        assert_eq!(
+            net::SocketAddr::from(([0, 0, 0, 0, 0, 0xFFFF, 0xCB00, 0x7107], 51234)),
+            *remote_addr
+        );
+        assert_eq!(*services, *local_services);
+        assert_eq!(net::SocketAddr::from(([0u16; 8], 0)), *local_addr);
+        assert_eq!(0x3A8E_5B1C_07D4_F926, *nonce);
+        assert_eq!(b"/Satoshi:27.0.0/", user_agent.as_slice());
+        assert_eq!(840_000, *last_block);
+        assert!(*relay);
+
+        assert_eq!(bytes[Header::SIZE..], payload.to_consensus_bytes());
+    }
+
+    #[test]
+    fn version_round_trip() {
+        let addr = net::SocketAddr::from(([203, 0, 113, 7], 8333));
+        let mut version = Payload::new_version(addr, addr, 42);
+        if let Payload::Version { relay, .. } = &mut version {
+            *relay = false;
+        }
+
+        let bytes = Message::from_payload(NetworkType::Mainnet, Command::Version, version.clone())
+            .to_bytes();
+        assert_eq!(
+            Header::SIZE + 80 + 1 + USER_AGENT.len() + 4 + 1,
+            bytes.len()
+        );
+        assert_eq!(Some(&0x00), bytes.last());
+
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(version.to_consensus_bytes(), decoded.to_consensus_bytes());
+        assert!(matches!(
+            decoded,
+            Payload::Version {
+                relay: false,
+                nonce: 42,
+                ..
             }
-            Command::Verack => Self::Verack,
+        ));
+    }
+
+    #[test]
+    fn version_user_agent() {
+        let addr = net::SocketAddr::from(([203, 0, 113, 7], 8333));
+        let version =
+            Payload::new_version_with_user_agent(addr, addr, 42, "/Satoshi:27.0.0/").unwrap();
+        assert!(matches!(
+            &version,
+            Payload::Version { user_agent, .. } if user_agent == b"/Satoshi:27.0.0/"
+        ));
+
+        assert!(matches!(
+            Payload::new_version_with_user_agent(addr, addr, 42, "/minerr:ü/"),
+            Err(Error::NonAsciiUserAgent)
+        ));
+        assert!(matches!(
+            Payload::new_version_with_user_agent(addr, addr, 42, &"a".repeat(257)),
+            Err(Error::UserAgentTooLong(257))
+        ));
+
+        // Set directly, it is still encoded.
+        let mut version = version;
+        if let Payload::Version { user_agent, .. } = &mut version {
+            *user_agent = "ü".as_bytes().to_vec();
+        }
+        assert!(!version.to_bytes().is_empty());
+    }
+
+    #[test]
+    fn version_short() {
+        let bytes = hex::decode(VERSION_60002).unwrap();
+        let payload = &bytes[Header::SIZE..];
+
+        // Only up to the remote address, as sent before version 106.
+        let header = Header::new(NetworkType::Mainnet, Command::Version, &payload[..46]);
+        let Payload::Version {
+            nonce,
+            user_agent,
+            last_block,
+            relay,
+            ..
+        } = Payload::from_bytes(&header, &payload[..46]).unwrap()
+        else {
+            panic!("Not a version payload");
         };
+        assert_eq!((0, 0, true), (nonce, last_block, relay));
+        assert!(user_agent.is_empty());
 
-        Ok(payload)
+        // Cut in the middle of the nonce.
+        let header = Header::new(NetworkType::Mainnet, Command::Version, &payload[..84]);
+        assert!(matches!(
+            Payload::from_bytes(&header, &payload[..84]),
+            Err(Error::Decode(_))
+        ));
+
+        // A user agent longer than 256 bytes.
+        let mut payload = payload[..80].to_vec();
+        payload.extend_from_slice(&[0xFD, 0x01, 0x01]);
+        payload.extend_from_slice(&[b'a'; 257]);
+        let header = Header::new(NetworkType::Mainnet, Command::Version, &payload);
+        assert!(matches!(
+            Payload::from_bytes(&header, &payload),
+            Err(Error::UserAgentTooLong(257))
+        ));
     }
 }
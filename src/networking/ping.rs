@@ -0,0 +1,210 @@
+use std::time::{Duration, Instant};
+
+use crate::networking::{error::Error, random_nonce};
+
+/// How often to ping a peer by default, the same as Bitcoin Core.
+pub const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(2 * 60);
+/// How long a peer may take to answer a ping by default, the same as Bitcoin Core.
+pub const DEFAULT_PING_TIMEOUT: Duration = Duration::from_secs(20 * 60);
+
+/// # Ping
+///
+/// Keeps track of the pings to a peer and the round-trip latency.
+///
+/// ```plaintext
+/// us                  peer
+///  | ---- ping(n) ---> |
+///  | <--- pong(n) ---- |
+/// ```
+///
+/// Only one ping is in flight at a time, the next one is sent an interval after the last one.
+/// A `pong` with another nonce is ignored.
+#[derive(Debug)]
+pub(super) struct Ping {
+    interval: Duration,
+    timeout: Duration,
+    last_sent: Option<Instant>,
+    /// The nonce and send time of the ping waiting for its pong.
+    in_flight: Option<(u64, Instant)>,
+    latency: Option<Duration>,
+    min_latency: Option<Duration>,
+}
+
+impl Ping {
+    pub(super) fn new(interval: Duration, timeout: Duration) -> Self {
+        Self {
+            interval,
+            timeout,
+            last_sent: None,
+            in_flight: None,
+            latency: None,
+            min_latency: None,
+        }
+    }
+
+    /// The nonce of a ping to send now, if one is due.
+    ///
+    /// Errors if the peer did not answer the last ping in time.
+    pub(super) fn poll(&mut self, now: Instant) -> Result<Option<u64>, Error> {
+        if let Some((_, sent)) = self.in_flight {
+            if now.saturating_duration_since(sent) > self.timeout {
+                return Err(Error::PingTimeout);
+            }
+
+            return Ok(None);
+        }
+
+        if self
+            .last_sent
+            .is_some_and(|last_sent| now.saturating_duration_since(last_sent) < self.interval)
+        {
+            return Ok(None);
+        }
+
+        let nonce = random_nonce();
+        self.last_sent = Some(now);
+        self.in_flight = Some((nonce, now));
+
+        Ok(Some(nonce))
+    }
+
+    /// When [`Self::poll`] has to be called next, to send the next ping or to detect a timeout.
+    pub(super) fn next_poll(&self, now: Instant) -> Instant {
+        match (self.in_flight, self.last_sent) {
+            (Some((_, sent)), _) => sent + self.timeout,
+            (None, Some(last_sent)) => last_sent + self.interval,
+            (None, None) => now,
+        }
+    }
+
+    /// Record the pong with `nonce`, returns whether it answered the ping in flight.
+    pub(super) fn pong(&mut self, nonce: u64, now: Instant) -> bool {
+        match self.in_flight {
+            Some((expected, sent)) if expected == nonce => {
+                let latency = now.saturating_duration_since(sent);
+                self.latency = Some(latency);
+                self.min_latency = Some(self.min_latency.map_or(latency, |min| min.min(latency)));
+                self.in_flight = None;
+
+                true
+            }
+            _ => {
+                log::debug!("Ignoring an unexpected pong with nonce {nonce}.");
+
+                false
+            }
+        }
+    }
+
+    /// The round-trip time of the last answered ping.
+    pub(super) fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+
+    /// The lowest round-trip time of all answered pings.
+    pub(super) fn min_latency(&self) -> Option<Duration> {
+        self.min_latency
+    }
+}
+
+#[cfg(test)]
+mod ping_test {
+    use std::{
+        net::TcpStream,
+        sync::mpsc,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        networking::{
+            ConnectOptions, Network, NetworkType, command::Command, error::Error,
+            frame::FrameDecoder, payload::Payload, ping::Ping,
+        },
+        test_util::{send, spawn_handshake_peer, wait_until},
+    };
+
+    /// Connect to a peer which finishes the handshake and then runs `peer`.
+    fn connect_to_peer(
+        ping_timeout: Duration,
+        peer: impl FnOnce(TcpStream, FrameDecoder) + Send + 'static,
+    ) -> Network {
+        let options = ConnectOptions {
+            ping_interval: Duration::from_millis(10),
+            ping_timeout,
+            ..Default::default()
+        };
+        Network::connect_with_options(spawn_handshake_peer(peer), NetworkType::Regtest, options)
+            .unwrap()
+    }
+
+    #[test]
+    fn ping_pong() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut ping = Ping::new(10 * second, 60 * second);
+
+        // The first ping is sent right away.
+        assert_eq!(start, ping.next_poll(start));
+        let nonce = ping.poll(start).unwrap().unwrap();
+        assert_eq!(start + 60 * second, ping.next_poll(start));
+        assert_eq!(None, ping.poll(start + 5 * second).unwrap());
+
+        assert!(!ping.pong(nonce.wrapping_add(1), start + second));
+        assert!(ping.pong(nonce, start + 3 * second));
+        assert!(!ping.pong(nonce, start + 4 * second));
+        assert_eq!(Some(3 * second), ping.latency());
+
+        // The next one an interval after the last.
+        assert_eq!(start + 10 * second, ping.next_poll(start + 4 * second));
+        assert_eq!(None, ping.poll(start + 9 * second).unwrap());
+        let nonce = ping.poll(start + 10 * second).unwrap().unwrap();
+        assert!(ping.pong(nonce, start + 15 * second));
+        assert_eq!(Some(5 * second), ping.latency());
+        assert_eq!(Some(3 * second), ping.min_latency());
+
+        let nonce = ping.poll(start + 20 * second).unwrap().unwrap();
+        assert_eq!(None, ping.poll(start + 80 * second).unwrap());
+        assert!(matches!(
+            ping.poll(start + 81 * second),
+            Err(Error::PingTimeout)
+        ));
+        assert!(ping.pong(nonce, start + 82 * second));
+    }
+
+    #[test]
+    fn keepalive() {
+        let (pong_sender, pong_receiver) = mpsc::channel();
+        let network = connect_to_peer(Duration::from_secs(5), move |mut stream, mut decoder| {
+            send(&mut stream, Command::Ping, Payload::Ping(42));
+
+            // Answer the pings, until the network disconnects.
+            while let Ok(message) = decoder.read_message(&mut stream) {
+                match message.payload() {
+                    Payload::Ping(nonce) => send(&mut stream, Command::Pong, Payload::Pong(*nonce)),
+                    Payload::Pong(nonce) => pong_sender.send(*nonce).unwrap(),
+                    _ => {}
+                }
+            }
+        });
+
+        assert_eq!(
+            42,
+            pong_receiver.recv_timeout(Duration::from_secs(5)).unwrap()
+        );
+        wait_until(|| network.latency().is_some());
+        assert!(network.min_latency() <= network.latency());
+        assert!(network.is_connected());
+        assert!(network.recv().is_none());
+    }
+
+    #[test]
+    fn timeout() {
+        let network = connect_to_peer(Duration::from_millis(50), |mut stream, mut decoder| {
+            // Never answer.
+            while decoder.read_message(&mut stream).is_ok() {}
+        });
+
+        wait_until(|| !network.is_connected());
+        assert_eq!(None, network.latency());
+    }
+}
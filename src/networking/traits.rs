@@ -7,9 +7,9 @@ pub trait FromToIpV6 {
 pub trait NetworkInformation {
     fn port(&self) -> u16;
 
-    /// The same as [`magic_number`] only as bytes in little-endian.
+    /// The magic bytes in the order they are sent over the wire.
     fn magic_bytes(&self) -> [u8; 4];
 
-    /// The same as [`magic_bytes`] only as a `u32` in little-endian.
+    /// The same as [`NetworkInformation::magic_bytes`] only as a big-endian `u32`.
     fn magic_number(&self) -> u32;
 }
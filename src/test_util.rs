@@ -0,0 +1,139 @@
+use std::{
+    fs,
+    io::Write,
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    block::BlockHeader,
+    hash::Hash,
+    networking::{
+        Network, NetworkType, command::Command, frame::FrameDecoder, message::Message,
+        payload::Payload,
+    },
+};
+
+/// The easiest regtest target.
+pub(crate) const REGTEST_TARGET: u32 = 0x207F_FFFF;
+
+/// A fresh directory in the system temp dir, removed on drop.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    /// `prefix` has to be unique per test, tests run in parallel.
+    pub(crate) fn new(prefix: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("btc_minerr_{}_{}", prefix, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        Self(path)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Mine a regtest header on top of `prev`.
+pub(crate) fn mine_header(prev: &BlockHeader, time: u32, target: u32) -> BlockHeader {
+    let mut header = BlockHeader {
+        version: 0x2000_0000,
+        prev_block_header_hash: prev.hash(),
+        merkle_root_hash: Hash::ZERO,
+        time,
+        target,
+        nonce: 0,
+    };
+    while !header.check_proof_of_work().unwrap() {
+        header.nonce += 1;
+    }
+
+    header
+}
+
+/// Mine `count` regtest headers on top of `prev`, the first one at `time`, one second apart.
+pub(crate) fn mine_chain(prev: &BlockHeader, count: u32, time: u32) -> Vec<BlockHeader> {
+    let mut headers: Vec<BlockHeader> = Vec::new();
+    for i in 0..count {
+        let prev = headers.last().unwrap_or(prev);
+        headers.push(mine_header(prev, time + i, REGTEST_TARGET));
+    }
+
+    headers
+}
+
+/// Listen on a local port and run `peer` on the first accepted connection in a thread.
+///
+/// The peer talks regtest, connect a [`Network`] to the returned address.
+pub(crate) fn spawn_peer(
+    peer: impl FnOnce(TcpStream, FrameDecoder) + Send + 'static,
+) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let stream = listener.accept().unwrap().0;
+        peer(stream, FrameDecoder::new(NetworkType::Regtest));
+    });
+
+    addr
+}
+
+/// [`spawn_peer`] which finishes the handshake before running `peer`.
+pub(crate) fn spawn_handshake_peer(
+    peer: impl FnOnce(TcpStream, FrameDecoder) + Send + 'static,
+) -> SocketAddr {
+    spawn_peer(|mut stream, mut decoder| {
+        accept_handshake(&mut stream, &mut decoder);
+        peer(stream, decoder);
+    })
+}
+
+/// Answer the `version` of the connecting [`Network`] with ours and a `verack`.
+///
+/// The `verack` of the network is left for the peer to read.
+pub(crate) fn accept_handshake(stream: &mut TcpStream, decoder: &mut FrameDecoder) {
+    let version = decoder.read_message(stream).unwrap();
+    assert_eq!(Command::Version, version.header().command());
+
+    let version =
+        Payload::new_version(stream.local_addr().unwrap(), stream.peer_addr().unwrap(), 1);
+    send(stream, Command::Version, version);
+    send(stream, Command::Verack, Payload::Verack);
+}
+
+/// Send a regtest message.
+pub(crate) fn send(stream: &mut TcpStream, command: Command, payload: Payload) {
+    stream
+        .write_all(&Message::from_payload(NetworkType::Regtest, command, payload).to_bytes())
+        .unwrap();
+}
+
+/// Wait up to 5 seconds for `condition`.
+pub(crate) fn wait_until(mut condition: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !condition() {
+        assert!(Instant::now() < deadline, "Timed out");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Wait up to 5 seconds for the next received message.
+pub(crate) fn recv(network: &Network) -> Message {
+    let mut message = None;
+    wait_until(|| {
+        message = network.recv();
+        message.is_some()
+    });
+
+    message.expect("The message was received.")
+}
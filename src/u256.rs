@@ -0,0 +1,490 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    ops::{Add, BitAnd, BitOr, Div, Mul, Not, Shl, Shr, Sub},
+};
+
+use crate::hash::Hash;
+
+/// A 256-bit unsigned integer.
+///
+/// Stored as four 64-bit limbs, least significant limb first.
+/// All arithmetic wraps on overflow, the same as Bitcoin Core´s `arith_uint256`.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct U256([u64; 4]);
+
+/// A compact `nBits` value decoded into a full 256-bit target.
+///
+/// See [Target](https://learnmeabitcoin.com/technical/mining/target/).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactTarget {
+    pub target: U256,
+    /// The sign bit (`0x00800000`) was set on a non-zero mantissa.
+    pub negative: bool,
+    /// The target does not fit into 256 bits.
+    pub overflow: bool,
+}
+
+impl U256 {
+    pub const ZERO: Self = Self([0, 0, 0, 0]);
+    pub const ONE: Self = Self([1, 0, 0, 0]);
+    pub const MAX: Self = Self([u64::MAX; 4]);
+
+    /// Create from four 64-bit limbs, least significant limb first.
+    pub const fn from_limbs(limbs: [u64; 4]) -> Self {
+        Self(limbs)
+    }
+
+    pub const fn from_u64(value: u64) -> Self {
+        Self([value, 0, 0, 0])
+    }
+
+    pub fn from_le_bytes(bytes: [u8; 32]) -> Self {
+        let mut limbs = [0u64; 4];
+        for (limb, chunk) in limbs.iter_mut().zip(bytes.chunks_exact(8)) {
+            let mut limb_bytes = [0u8; 8];
+            limb_bytes.copy_from_slice(chunk);
+            *limb = u64::from_le_bytes(limb_bytes);
+        }
+
+        Self(limbs)
+    }
+
+    pub fn from_be_bytes(mut bytes: [u8; 32]) -> Self {
+        bytes.reverse();
+        Self::from_le_bytes(bytes)
+    }
+
+    pub fn to_le_bytes(self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (chunk, limb) in bytes.chunks_exact_mut(8).zip(self.0) {
+            chunk.copy_from_slice(&limb.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    pub fn to_be_bytes(self) -> [u8; 32] {
+        let mut bytes = self.to_le_bytes();
+        bytes.reverse();
+        bytes
+    }
+
+    /// The lowest 64 bits.
+    pub const fn low_u64(&self) -> u64 {
+        self.0[0]
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0u64; 4]
+    }
+
+    /// The position of the highest set bit plus one; `0` for zero.
+    pub fn bits(&self) -> u32 {
+        for (i, limb) in self.0.iter().enumerate().rev() {
+            if *limb != 0 {
+                return 64 * i as u32 + (64 - limb.leading_zeros());
+            }
+        }
+
+        0
+    }
+
+    /// Decode a compact `nBits` value the same way as Bitcoin Core´s `SetCompact`.
+    ///
+    /// The compact format is a base-256 scientific notation:
+    /// the highest byte is the size in bytes, the lower 23 bits are the mantissa
+    /// and `0x00800000` is the sign bit.
+    pub fn from_compact(compact: u32) -> CompactTarget {
+        let size = compact >> 24;
+        let mut word = compact & 0x007F_FFFF;
+
+        let target = if size <= 3 {
+            word >>= 8 * (3 - size);
+            Self::from_u64(word as u64)
+        } else {
+            Self::from_u64(word as u64) << (8 * (size - 3))
+        };
+
+        let negative = word != 0 && (compact & 0x0080_0000) != 0;
+        let overflow =
+            word != 0 && (size > 34 || (word > 0xFF && size > 33) || (word > 0xFFFF && size > 32));
+
+        CompactTarget {
+            target,
+            negative,
+            overflow,
+        }
+    }
+
+    /// Encode into a compact `nBits` value the same way as Bitcoin Core´s `GetCompact`.
+    ///
+    /// Precision below the 3 byte mantissa is lost.
+    pub fn to_compact(&self, negative: bool) -> u32 {
+        let mut size = self.bits().div_ceil(8);
+        let mut compact = if size <= 3 {
+            (self.low_u64() << (8 * (3 - size))) as u32
+        } else {
+            (*self >> (8 * (size - 3))).low_u64() as u32
+        };
+
+        // The mantissa would have its sign bit set, so make room for it.
+        if compact & 0x0080_0000 != 0 {
+            compact >>= 8;
+            size += 1;
+        }
+        compact |= size << 24;
+        if negative && compact & 0x007F_FFFF != 0 {
+            compact |= 0x0080_0000;
+        }
+
+        compact
+    }
+
+    /// Approximate this value as a float.
+    pub fn to_f64(&self) -> f64 {
+        self.0.iter().rev().fold(0.0, |acc, limb| {
+            acc * 18_446_744_073_709_551_616.0 + *limb as f64
+        })
+    }
+
+    fn overflowing_add(self, rhs: Self) -> (Self, bool) {
+        let mut out = [0u64; 4];
+        let mut carry = false;
+        for (i, limb) in out.iter_mut().enumerate() {
+            let (sum, c1) = self.0[i].overflowing_add(rhs.0[i]);
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = c1 || c2;
+        }
+
+        (Self(out), carry)
+    }
+
+    /// Division with remainder using binary long division.
+    ///
+    /// Panics on division by zero.
+    pub fn div_rem(self, rhs: Self) -> (Self, Self) {
+        assert!(!rhs.is_zero(), "Division of U256 by zero!");
+
+        if self < rhs {
+            return (Self::ZERO, self);
+        }
+
+        let mut quotient = Self::ZERO;
+        let mut remainder = Self::ZERO;
+        for bit in (0..self.bits()).rev() {
+            remainder = remainder << 1;
+            if self.bit(bit) {
+                remainder.0[0] |= 1;
+            }
+            if remainder >= rhs {
+                remainder = remainder - rhs;
+                quotient.0[(bit / 64) as usize] |= 1 << (bit % 64);
+            }
+        }
+
+        (quotient, remainder)
+    }
+
+    fn bit(&self, bit: u32) -> bool {
+        (self.0[(bit / 64) as usize] >> (bit % 64)) & 1 == 1
+    }
+}
+
+impl CompactTarget {
+    /// Whether the target is usable as a proof of work target.
+    ///
+    /// Bitcoin Core rejects negative, zero and overflowing targets.
+    pub fn is_valid(&self) -> bool {
+        !self.negative && !self.overflow && !self.target.is_zero()
+    }
+}
+
+impl From<u64> for U256 {
+    fn from(value: u64) -> Self {
+        Self::from_u64(value)
+    }
+}
+
+impl From<Hash> for U256 {
+    /// A hash is interpreted as a little-endian integer in natural byte order.
+    fn from(hash: Hash) -> Self {
+        Self::from_le_bytes(hash.to_bytes())
+    }
+}
+
+impl From<U256> for Hash {
+    fn from(value: U256) -> Self {
+        Hash::from_natural_bytes(value.to_le_bytes())
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.iter().rev().cmp(other.0.iter().rev())
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for U256 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        self.overflowing_add(rhs).0
+    }
+}
+
+impl Sub for U256 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        // Two's complement: a - b = a + !b + 1
+        self.overflowing_add(!rhs).0.overflowing_add(Self::ONE).0
+    }
+}
+
+impl Mul for U256 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut out = [0u64; 4];
+        for i in 0..4 {
+            let mut carry = 0u128;
+            for j in 0..4 - i {
+                let product = self.0[i] as u128 * rhs.0[j] as u128 + out[i + j] as u128 + carry;
+                out[i + j] = product as u64;
+                carry = product >> 64;
+            }
+        }
+
+        Self(out)
+    }
+}
+
+impl Div for U256 {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        self.div_rem(rhs).0
+    }
+}
+
+impl Not for U256 {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        Self(self.0.map(|limb| !limb))
+    }
+}
+
+impl BitAnd for U256 {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Self([
+            self.0[0] & rhs.0[0],
+            self.0[1] & rhs.0[1],
+            self.0[2] & rhs.0[2],
+            self.0[3] & rhs.0[3],
+        ])
+    }
+}
+
+impl BitOr for U256 {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self([
+            self.0[0] | rhs.0[0],
+            self.0[1] | rhs.0[1],
+            self.0[2] | rhs.0[2],
+            self.0[3] | rhs.0[3],
+        ])
+    }
+}
+
+impl Shl<u32> for U256 {
+    type Output = Self;
+
+    fn shl(self, shift: u32) -> Self::Output {
+        if shift >= 256 {
+            return Self::ZERO;
+        }
+
+        let limb_shift = (shift / 64) as usize;
+        let bit_shift = shift % 64;
+        let mut out = [0u64; 4];
+        for (i, limb) in out.iter_mut().enumerate().skip(limb_shift) {
+            *limb = self.0[i - limb_shift] << bit_shift;
+            if bit_shift > 0 && i > limb_shift {
+                *limb |= self.0[i - limb_shift - 1] >> (64 - bit_shift);
+            }
+        }
+
+        Self(out)
+    }
+}
+
+impl Shr<u32> for U256 {
+    type Output = Self;
+
+    fn shr(self, shift: u32) -> Self::Output {
+        if shift >= 256 {
+            return Self::ZERO;
+        }
+
+        let limb_shift = (shift / 64) as usize;
+        let bit_shift = shift % 64;
+        let mut out = [0u64; 4];
+        for (i, limb) in out.iter_mut().enumerate().take(4 - limb_shift) {
+            *limb = self.0[i + limb_shift] >> bit_shift;
+            if bit_shift > 0 && i + limb_shift + 1 < 4 {
+                *limb |= self.0[i + limb_shift + 1] << (64 - bit_shift);
+            }
+        }
+
+        Self(out)
+    }
+}
+
+impl fmt::LowerHex for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for limb in self.0.iter().rev() {
+            write!(f, "{:016x}", limb)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:x}", self)
+    }
+}
+
+impl fmt::Debug for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "U256({:x})", self)
+    }
+}
+
+#[cfg(test)]
+mod u256_test {
+    use super::U256;
+
+    #[test]
+    fn arithmetic() {
+        let a = U256::from_u64(u64::MAX);
+        let b = U256::ONE;
+
+        assert_eq!(a + b, U256::ONE << 64);
+        assert_eq!((a + b) - b, a);
+        assert_eq!(U256::ZERO - U256::ONE, U256::MAX);
+        assert_eq!(U256::MAX + U256::ONE, U256::ZERO);
+        assert_eq!(a * a, (U256::ONE << 128) - (U256::ONE << 65) + U256::ONE);
+        assert_eq!((U256::ONE << 200) / (U256::ONE << 100), U256::ONE << 100);
+        assert_eq!(
+            U256::from_u64(1000).div_rem(U256::from_u64(7)),
+            (U256::from_u64(142), U256::from_u64(6))
+        );
+        assert_eq!((U256::ONE << 255) >> 255, U256::ONE);
+        assert_eq!(U256::ONE << 256, U256::ZERO);
+        assert_eq!(U256::MAX.bits(), 256);
+        assert_eq!(U256::ZERO.bits(), 0);
+    }
+
+    #[test]
+    fn byte_order() {
+        let mut bytes = [0u8; 32];
+        bytes[0] = 0x01;
+        bytes[31] = 0x80;
+        let value = U256::from_le_bytes(bytes);
+
+        assert_eq!(value, (U256::ONE << 255) + U256::ONE);
+        assert_eq!(value.to_le_bytes(), bytes);
+        assert_eq!(U256::from_be_bytes(value.to_be_bytes()), value);
+    }
+
+    /// Test vectors from Bitcoin Core´s `arith_uint256_tests`.
+    #[test]
+    fn compact() {
+        for zero in [
+            0x0000_0000,
+            0x0012_3456,
+            0x0100_3456,
+            0x0200_0056,
+            0x0300_0000,
+            0x0400_0000,
+            0x0092_3456,
+            0x0180_3456,
+            0x0280_0056,
+            0x0380_0000,
+            0x0480_0000,
+        ] {
+            let decoded = U256::from_compact(zero);
+            assert!(decoded.target.is_zero(), "{:#010x}", zero);
+            assert!(!decoded.negative, "{:#010x}", zero);
+            assert!(!decoded.overflow, "{:#010x}", zero);
+            assert_eq!(decoded.target.to_compact(false), 0);
+        }
+
+        let decoded = U256::from_compact(0x0112_3456);
+        assert_eq!(decoded.target, U256::from_u64(0x12));
+        assert_eq!(decoded.target.to_compact(false), 0x0112_0000);
+
+        assert_eq!(U256::from_u64(0x80).to_compact(false), 0x0200_8000);
+
+        let decoded = U256::from_compact(0x01FE_DCBA);
+        assert_eq!(decoded.target, U256::from_u64(0x7E));
+        assert!(decoded.negative);
+        assert_eq!(decoded.target.to_compact(true), 0x01FE_0000);
+
+        let decoded = U256::from_compact(0x0212_3456);
+        assert_eq!(decoded.target, U256::from_u64(0x1234));
+        assert_eq!(decoded.target.to_compact(false), 0x0212_3400);
+
+        let decoded = U256::from_compact(0x0312_3456);
+        assert_eq!(decoded.target, U256::from_u64(0x12_3456));
+        assert_eq!(decoded.target.to_compact(false), 0x0312_3456);
+
+        let decoded = U256::from_compact(0x0412_3456);
+        assert_eq!(decoded.target, U256::from_u64(0x1234_5600));
+        assert_eq!(decoded.target.to_compact(false), 0x0412_3456);
+
+        let decoded = U256::from_compact(0x0492_3456);
+        assert_eq!(decoded.target, U256::from_u64(0x1234_5600));
+        assert!(decoded.negative);
+        assert_eq!(decoded.target.to_compact(true), 0x0492_3456);
+
+        let decoded = U256::from_compact(0x0500_9234);
+        assert_eq!(decoded.target, U256::from_u64(0x9234_0000));
+        assert_eq!(decoded.target.to_compact(false), 0x0500_9234);
+
+        let decoded = U256::from_compact(0x2012_3456);
+        assert_eq!(decoded.target, U256::from_u64(0x12_3456) << (8 * 29));
+        assert_eq!(decoded.target.to_compact(false), 0x2012_3456);
+
+        let decoded = U256::from_compact(0xFF12_3456);
+        assert!(!decoded.negative);
+        assert!(decoded.overflow);
+    }
+
+    #[test]
+    fn compact_difficulty_1() {
+        let decoded = U256::from_compact(0x1D00_FFFF);
+
+        assert!(decoded.is_valid());
+        assert_eq!(
+            format!("{:x}", decoded.target),
+            "00000000ffff0000000000000000000000000000000000000000000000000000"
+        );
+        assert_eq!(decoded.target.to_compact(false), 0x1D00_FFFF);
+    }
+}